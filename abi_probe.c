@@ -0,0 +1,62 @@
+/* Reports type formats, sizes and offsets of MPFR types and macros to build.rs.
+ *
+ * The probe is only compiled, never run, so that it works when cross-compiling: every
+ * value is stored in the object file as a line `MPFR_PROBE name value`, the value being
+ * spelled out in decimal by constant expressions, and build.rs looks for these lines. */
+#include <stddef.h>
+#include <stdint.h>
+#include <mpfr.h>
+
+#define TAG(name) "MPFR_PROBE " #name " "
+
+/* Magnitude of a signed value, which may be the most negative one */
+#define MAG(v) ((v) < 0 ? (uintmax_t) -((v) + 1) + 1 : (uintmax_t) (v))
+#define D(v, p) (char) ('0' + MAG(v) / (p) % 10)
+
+#define VALUE(name, v)                                                                  \
+    const struct { char tag[sizeof(TAG(name)) - 1]; char digits[22]; } probe_##name = { \
+        TAG(name), {                                                                    \
+            (v) < 0 ? '-' : '+',                                                        \
+            D(v, 10000000000000000000u), D(v, 1000000000000000000u),                    \
+            D(v, 100000000000000000u), D(v, 10000000000000000u),                        \
+            D(v, 1000000000000000u), D(v, 100000000000000u), D(v, 10000000000000u),     \
+            D(v, 1000000000000u), D(v, 100000000000u), D(v, 10000000000u),              \
+            D(v, 1000000000u), D(v, 100000000u), D(v, 10000000u), D(v, 1000000u),       \
+            D(v, 100000u), D(v, 10000u), D(v, 1000u), D(v, 100u), D(v, 10u), D(v, 1u),  \
+            '\n'                                                                        \
+        }                                                                               \
+    }
+
+#define SIZE(name, type) VALUE(sizeof_##name, sizeof(type))
+#define OFFSET(field) VALUE(offsetof_##field, offsetof(__mpfr_struct, field))
+
+VALUE(prec_format, _MPFR_PREC_FORMAT);
+VALUE(exp_format, _MPFR_EXP_FORMAT);
+VALUE(version_major, MPFR_VERSION_MAJOR);
+VALUE(version_minor, MPFR_VERSION_MINOR);
+VALUE(version_patchlevel, MPFR_VERSION_PATCHLEVEL);
+const char probe_version_string[] = TAG(version_string) "\"" MPFR_VERSION_STRING "\"\n";
+VALUE(prec_min, MPFR_PREC_MIN);
+VALUE(prec_max, MPFR_PREC_MAX);
+VALUE(emin_default, MPFR_EMIN_DEFAULT);
+VALUE(emax_default, MPFR_EMAX_DEFAULT);
+VALUE(exp_max, __MPFR_EXP_MAX);
+VALUE(exp_nan, __MPFR_EXP_NAN);
+VALUE(exp_zero, __MPFR_EXP_ZERO);
+VALUE(exp_inf, __MPFR_EXP_INF);
+
+SIZE(short, short);
+SIZE(int, int);
+SIZE(long, long);
+SIZE(intmax_t, intmax_t);
+SIZE(pointer, void *);
+
+SIZE(mpfr_prec_t, mpfr_prec_t);
+SIZE(mpfr_sign_t, mpfr_sign_t);
+SIZE(mpfr_exp_t, mpfr_exp_t);
+SIZE(__mpfr_struct, __mpfr_struct);
+
+OFFSET(_mpfr_prec);
+OFFSET(_mpfr_sign);
+OFFSET(_mpfr_exp);
+OFFSET(_mpfr_d);
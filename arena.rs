@@ -0,0 +1,137 @@
+//! Arena allocating significands of many values of the same precision at once.
+//!
+//! All limbs live in a single allocation made when the arena is created; values handed out
+//! by the arena borrow it and are released all together when it is reset or dropped, so
+//! there is no per-value `mpfr_init2`/`mpfr_clear`.
+
+use std::cell::{Cell, UnsafeCell};
+use std::mem;
+use libc::c_void;
+use gmp::mp_limb_t;
+
+use super::{__mpfr_struct, mpfr_ptr, mpfr_srcptr, mpfr_prec_t, MPFR_NAN_KIND, MPFR_PREC_MIN};
+use inline::{mpfr_custom_get_size, mpfr_custom_init_set};
+
+pub struct Arena {
+    prec: mpfr_prec_t,
+    limbs_per_value: usize,
+    capacity: usize,
+    used: Cell<usize>,
+    // MPFR writes to the limbs of values handed out through a shared borrow
+    storage: UnsafeCell<Vec<mp_limb_t>>
+}
+
+impl Arena {
+    // Allocates storage for `capacity` values of precision `prec`
+    pub fn new(prec: mpfr_prec_t, capacity: usize) -> Arena {
+        assert!(prec >= MPFR_PREC_MIN, "precision {} is less than MPFR_PREC_MIN", prec);
+        let size = unsafe { mpfr_custom_get_size(prec) } as usize;
+        let limbs_per_value = size / mem::size_of::<mp_limb_t>();
+        let storage = vec![0; limbs_per_value * capacity];
+        Arena {
+            prec: prec,
+            limbs_per_value: limbs_per_value,
+            capacity: capacity,
+            used: Cell::new(0),
+            storage: UnsafeCell::new(storage)
+        }
+    }
+
+    #[inline]
+    pub fn prec(&self) -> mpfr_prec_t { self.prec }
+
+    #[inline]
+    pub fn capacity(&self) -> usize { self.capacity }
+
+    #[inline]
+    pub fn len(&self) -> usize { self.used.get() }
+
+    // Returns a new NaN value, or None if the arena is full
+    pub fn alloc(&self) -> Option<ArenaFloat> {
+        let index = self.used.get();
+        if index == self.capacity {
+            return None;
+        }
+        self.used.set(index + 1);
+
+        let mut raw: __mpfr_struct = unsafe { mem::zeroed() };
+        unsafe {
+            let storage = &mut *self.storage.get();
+            let limbs = storage.as_mut_ptr().offset((index * self.limbs_per_value) as isize);
+            mpfr_custom_init_set(&mut raw, MPFR_NAN_KIND as ::libc::c_int, 0, self.prec,
+                                 limbs as *mut c_void);
+        }
+        Some(ArenaFloat { raw: raw, _arena: self })
+    }
+
+    // Makes all storage available again; no values can be alive at this point
+    pub fn reset(&mut self) {
+        self.used.set(0);
+    }
+}
+
+// A value whose significand is owned by an arena
+pub struct ArenaFloat<'a> {
+    raw: __mpfr_struct,
+    _arena: &'a Arena
+}
+
+impl<'a> ArenaFloat<'a> {
+    #[inline]
+    pub fn prec(&self) -> mpfr_prec_t { self.raw._mpfr_prec }
+
+    #[inline]
+    pub fn as_ptr(&self) -> mpfr_srcptr { &self.raw }
+
+    #[inline]
+    pub fn as_mut_ptr(&mut self) -> mpfr_ptr { &mut self.raw }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Arena;
+    use super::super::{MPFR_RNDN, mpfr_set_d, mpfr_set_si, mpfr_get_d, mpfr_mul, mpfr_nan_p};
+
+    #[test]
+    fn alloc_up_to_capacity() {
+        let mut arena = Arena::new(100, 3);
+        {
+            let values: Vec<_> = (0..3).map(|_| arena.alloc()).collect();
+            assert!(values.iter().all(|v| v.is_some()));
+            assert!(arena.alloc().is_none());
+            assert_eq!(arena.len(), 3);
+            for v in values.iter() {
+                let v = v.as_ref().unwrap();
+                assert_eq!(v.prec(), 100);
+                assert!(unsafe { mpfr_nan_p(v.as_ptr()) } != 0);
+            }
+        }
+        arena.reset();
+        assert_eq!(arena.len(), 0);
+        assert!(arena.alloc().is_some());
+        assert!(Arena::new(53, 0).alloc().is_none());
+    }
+
+    #[test]
+    fn values_are_independent() {
+        let arena = Arena::new(200, 3);
+        let mut a = arena.alloc().unwrap();
+        let mut b = arena.alloc().unwrap();
+        let mut c = arena.alloc().unwrap();
+        unsafe {
+            mpfr_set_d(a.as_mut_ptr(), 1.5, MPFR_RNDN);
+            mpfr_set_si(b.as_mut_ptr(), -2, MPFR_RNDN);
+            mpfr_mul(c.as_mut_ptr(), a.as_ptr(), b.as_ptr(), MPFR_RNDN);
+            mpfr_set_d(a.as_mut_ptr(), 0.25, MPFR_RNDN);
+            assert_eq!(mpfr_get_d(a.as_ptr(), MPFR_RNDN), 0.25);
+            assert_eq!(mpfr_get_d(b.as_ptr(), MPFR_RNDN), -2.0);
+            assert_eq!(mpfr_get_d(c.as_ptr(), MPFR_RNDN), -3.0);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn precision_below_minimum() {
+        Arena::new(1, 10);
+    }
+}
@@ -0,0 +1,359 @@
+//! Ball (midpoint-radius) arithmetic.
+//!
+//! A ball holds a midpoint of arbitrary precision and a radius of `RAD_PREC` bits which is
+//! always rounded up. Each operation computes the midpoint with round to nearest and adds
+//! to the propagated radius a bound of the rounding error, taken from the ternary value of
+//! the midpoint computation and widened to the smallest positive number when the midpoint
+//! may have underflowed. The exact result of an operation on any points of the
+//! argument balls lies in the resulting ball.
+
+use libc::{c_int, c_ulong};
+
+use super::{mpfr_prec_t, mpfr_exp_t, MPFR_RNDN, MPFR_RNDU, MPFR_RNDD};
+use float::Mpfr;
+use special::DomainError;
+use inline::{mpfr_regular_p, mpfr_get_exp};
+
+// Precision of radii
+pub const RAD_PREC: mpfr_prec_t = 30;
+
+fn abs_up(x: &Mpfr) -> Mpfr {
+    let mut r = Mpfr::new(RAD_PREC);
+    unsafe { super::mpfr_abs(r.as_mut_ptr(), x.as_ptr(), MPFR_RNDU) };
+    r
+}
+
+fn add_up(x: &Mpfr, y: &Mpfr) -> Mpfr {
+    let mut r = Mpfr::new(RAD_PREC);
+    unsafe { super::mpfr_add(r.as_mut_ptr(), x.as_ptr(), y.as_ptr(), MPFR_RNDU) };
+    r
+}
+
+fn mul_up(x: &Mpfr, y: &Mpfr) -> Mpfr {
+    let mut r = Mpfr::new(RAD_PREC);
+    unsafe { super::mpfr_mul(r.as_mut_ptr(), x.as_ptr(), y.as_ptr(), MPFR_RNDU) };
+    r
+}
+
+// Bound of the error of a midpoint rounded to nearest with the given ternary value
+fn rounding_error(m: &Mpfr, ternary: c_int) -> Mpfr {
+    let mut r = Mpfr::zero(RAD_PREC);
+    if ternary == 0 {
+        return r;
+    }
+    unsafe {
+        let emin = super::mpfr_get_emin();
+        if m.sgn() == 0 || (mpfr_regular_p(m.as_ptr()) != 0 && mpfr_get_exp(m.as_ptr()) == emin) {
+            // Possibly an underflow, to zero or to the smallest number 2^(emin-1), with an
+            // error of at most 2^(emin-2) in either case
+            super::mpfr_set_ui_2exp(r.as_mut_ptr(), 1 as c_ulong, emin - 1, MPFR_RNDU);
+        } else if mpfr_regular_p(m.as_ptr()) != 0 {
+            // Half an ulp
+            let e = mpfr_get_exp(m.as_ptr()) - m.prec() as mpfr_exp_t - 1;
+            super::mpfr_set_ui_2exp(r.as_mut_ptr(), 1 as c_ulong, e, MPFR_RNDU);
+        } else {
+            // Overflow
+            super::mpfr_set_inf(r.as_mut_ptr(), 1);
+        }
+    }
+    r
+}
+
+pub struct Ball {
+    mid: Mpfr,
+    rad: Mpfr
+}
+
+impl Ball {
+    // The ball of radius zero around x
+    pub fn exact(x: &Mpfr) -> Ball {
+        Ball { mid: x.clone(), rad: Mpfr::zero(RAD_PREC) }
+    }
+
+    // The ball around x rounded to `prec` bits, containing x
+    pub fn new(x: &Mpfr, prec: mpfr_prec_t) -> Ball {
+        let mut mid = Mpfr::new(prec);
+        let t = unsafe { super::mpfr_set(mid.as_mut_ptr(), x.as_ptr(), MPFR_RNDN) };
+        let rad = rounding_error(&mid, t);
+        Ball { mid: mid, rad: rad }
+    }
+
+    // The ball around mid with the given radius, rounded up to RAD_PREC bits
+    pub fn with_radius(mid: &Mpfr, rad: &Mpfr) -> Ball {
+        Ball { mid: mid.clone(), rad: abs_up(rad) }
+    }
+
+    pub fn from_f64(x: f64, prec: mpfr_prec_t) -> Ball {
+        Ball::new(&Mpfr::from_f64(x, 53), prec)
+    }
+
+    #[inline]
+    pub fn mid(&self) -> &Mpfr { &self.mid }
+
+    #[inline]
+    pub fn rad(&self) -> &Mpfr { &self.rad }
+
+    #[inline]
+    pub fn prec(&self) -> mpfr_prec_t { self.mid.prec() }
+
+    // Lower bound of the ball with the precision of the midpoint
+    pub fn lower(&self) -> Mpfr {
+        let mut r = Mpfr::new(self.prec());
+        unsafe { super::mpfr_sub(r.as_mut_ptr(), self.mid.as_ptr(), self.rad.as_ptr(), MPFR_RNDD) };
+        r
+    }
+
+    // Upper bound of the ball with the precision of the midpoint
+    pub fn upper(&self) -> Mpfr {
+        let mut r = Mpfr::new(self.prec());
+        unsafe { super::mpfr_add(r.as_mut_ptr(), self.mid.as_ptr(), self.rad.as_ptr(), MPFR_RNDU) };
+        r
+    }
+
+    pub fn contains(&self, x: &Mpfr) -> bool {
+        unsafe {
+            super::mpfr_lessequal_p(self.lower().as_ptr(), x.as_ptr()) != 0 &&
+                super::mpfr_lessequal_p(x.as_ptr(), self.upper().as_ptr()) != 0
+        }
+    }
+
+    // Number of bits of the midpoint which are certainly correct relative to its magnitude,
+    // i.e. the difference of the exponents of the midpoint and the radius, or the precision
+    // of the midpoint if the ball is exact. Zero if nothing is certain.
+    pub fn accurate_bits(&self) -> mpfr_prec_t {
+        unsafe {
+            if self.rad.sgn() == 0 && mpfr_regular_p(self.mid.as_ptr()) != 0 {
+                return self.prec();
+            }
+            if mpfr_regular_p(self.mid.as_ptr()) == 0 || mpfr_regular_p(self.rad.as_ptr()) == 0 {
+                return 0;
+            }
+            let bits = mpfr_get_exp(self.mid.as_ptr()) - mpfr_get_exp(self.rad.as_ptr()) - 1;
+            if bits <= 0 { 0 } else { ::std::cmp::min(bits as mpfr_prec_t, self.prec()) }
+        }
+    }
+
+    pub fn add(&self, other: &Ball) -> Ball {
+        let mut mid = Mpfr::new(self.prec());
+        let t = unsafe {
+            super::mpfr_add(mid.as_mut_ptr(), self.mid.as_ptr(), other.mid.as_ptr(), MPFR_RNDN)
+        };
+        let rad = add_up(&add_up(&self.rad, &other.rad), &rounding_error(&mid, t));
+        Ball { mid: mid, rad: rad }
+    }
+
+    pub fn sub(&self, other: &Ball) -> Ball {
+        let mut mid = Mpfr::new(self.prec());
+        let t = unsafe {
+            super::mpfr_sub(mid.as_mut_ptr(), self.mid.as_ptr(), other.mid.as_ptr(), MPFR_RNDN)
+        };
+        let rad = add_up(&add_up(&self.rad, &other.rad), &rounding_error(&mid, t));
+        Ball { mid: mid, rad: rad }
+    }
+
+    // |xy - x'y'| <= |x'|r_y + |y'|r_x + r_x r_y for x', y' the midpoints
+    pub fn mul(&self, other: &Ball) -> Ball {
+        let mut mid = Mpfr::new(self.prec());
+        let t = unsafe {
+            super::mpfr_mul(mid.as_mut_ptr(), self.mid.as_ptr(), other.mid.as_ptr(), MPFR_RNDN)
+        };
+        let prop = add_up(&add_up(&mul_up(&abs_up(&self.mid), &other.rad),
+                                  &mul_up(&abs_up(&other.mid), &self.rad)),
+                          &mul_up(&self.rad, &other.rad));
+        Ball { rad: add_up(&prop, &rounding_error(&mid, t)), mid: mid }
+    }
+
+    // |x/y - x'/y'| <= (|x'| r_y + |y'| r_x) / (|y'| (|y'| - r_y)); fails if the ball of the
+    // divisor contains zero
+    pub fn div(&self, other: &Ball) -> Result<Ball, DomainError> {
+        let mut y = Mpfr::new(RAD_PREC);
+        let mut low = Mpfr::new(RAD_PREC);
+        unsafe {
+            super::mpfr_abs(y.as_mut_ptr(), other.mid.as_ptr(), MPFR_RNDD);
+            super::mpfr_sub(low.as_mut_ptr(), y.as_ptr(), other.rad.as_ptr(), MPFR_RNDD);
+        }
+        if low.sgn() <= 0 {
+            return Err(DomainError { function: "mpfr_div" });
+        }
+        let mut mid = Mpfr::new(self.prec());
+        let mut prop = add_up(&mul_up(&abs_up(&self.mid), &other.rad),
+                              &mul_up(&abs_up(&other.mid), &self.rad));
+        let t = unsafe {
+            super::mpfr_mul(low.as_mut_ptr(), low.as_ptr(), y.as_ptr(), MPFR_RNDD);
+            super::mpfr_div(prop.as_mut_ptr(), prop.as_ptr(), low.as_ptr(), MPFR_RNDU);
+            super::mpfr_div(mid.as_mut_ptr(), self.mid.as_ptr(), other.mid.as_ptr(), MPFR_RNDN)
+        };
+        Ok(Ball { rad: add_up(&prop, &rounding_error(&mid, t)), mid: mid })
+    }
+
+    // |exp(x) - exp(x')| <= exp(x') (exp(r) - 1)
+    pub fn exp(&self) -> Ball {
+        let mut mid = Mpfr::new(self.prec());
+        let mut e = Mpfr::new(RAD_PREC);
+        let mut g = Mpfr::new(RAD_PREC);
+        let t = unsafe {
+            super::mpfr_exp(e.as_mut_ptr(), self.mid.as_ptr(), MPFR_RNDU);
+            super::mpfr_expm1(g.as_mut_ptr(), self.rad.as_ptr(), MPFR_RNDU);
+            super::mpfr_exp(mid.as_mut_ptr(), self.mid.as_ptr(), MPFR_RNDN)
+        };
+        Ball { rad: add_up(&mul_up(&e, &g), &rounding_error(&mid, t)), mid: mid }
+    }
+
+    // |log(x) - log(x')| <= -log(1 - r/x'); fails unless the ball is positive
+    pub fn log(&self) -> Result<Ball, DomainError> {
+        if self.lower().sgn() <= 0 {
+            return Err(DomainError { function: "mpfr_log" });
+        }
+        let mut mid = Mpfr::new(self.prec());
+        let mut q = Mpfr::new(RAD_PREC);
+        let mut l = Mpfr::new(RAD_PREC);
+        let t = unsafe {
+            // log1p is increasing, so a lower bound of log1p(-q) comes from an upper bound of q
+            super::mpfr_div(q.as_mut_ptr(), self.rad.as_ptr(), self.mid.as_ptr(), MPFR_RNDU);
+            super::mpfr_neg(q.as_mut_ptr(), q.as_ptr(), MPFR_RNDN);
+            super::mpfr_log1p(l.as_mut_ptr(), q.as_ptr(), MPFR_RNDD);
+            super::mpfr_neg(l.as_mut_ptr(), l.as_ptr(), MPFR_RNDN);
+            super::mpfr_log(mid.as_mut_ptr(), self.mid.as_ptr(), MPFR_RNDN)
+        };
+        if l.is_nan() {
+            // r/x' rounded up past 1
+            unsafe { super::mpfr_set_inf(l.as_mut_ptr(), 1) };
+        }
+        Ok(Ball { rad: add_up(&l, &rounding_error(&mid, t)), mid: mid })
+    }
+
+    // sin is 1-Lipschitz: |sin(x) - sin(x')| <= r
+    pub fn sin(&self) -> Ball {
+        let mut mid = Mpfr::new(self.prec());
+        let t = unsafe { super::mpfr_sin(mid.as_mut_ptr(), self.mid.as_ptr(), MPFR_RNDN) };
+        Ball { rad: add_up(&self.rad, &rounding_error(&mid, t)), mid: mid }
+    }
+
+    // cos is 1-Lipschitz: |cos(x) - cos(x')| <= r
+    pub fn cos(&self) -> Ball {
+        let mut mid = Mpfr::new(self.prec());
+        let t = unsafe { super::mpfr_cos(mid.as_mut_ptr(), self.mid.as_ptr(), MPFR_RNDN) };
+        Ball { rad: add_up(&self.rad, &rounding_error(&mid, t)), mid: mid }
+    }
+}
+
+impl Clone for Ball {
+    fn clone(&self) -> Ball {
+        Ball { mid: self.mid.clone(), rad: self.rad.clone() }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::num::Float;
+
+    use super::Ball;
+    use super::super::{mpfr_prec_t, mpfr_exp_t, MPFR_RNDN};
+    use float::{Mpfr, Fn1, Fn2};
+    use context;
+
+    // Precision of the reference results
+    const REF: mpfr_prec_t = 1000;
+
+    // Balls with exact and inexact midpoints, and with large radii
+    fn balls() -> Vec<Ball> {
+        let third = Mpfr::from_ui(1, REF).apply2(super::super::mpfr_div, &Mpfr::from_ui(3, REF),
+                                                 REF, MPFR_RNDN);
+        vec![Ball::from_f64(1.5, 53), Ball::from_f64(-0.75, 53), Ball::new(&third, 53),
+             Ball::with_radius(&Mpfr::from_f64(2.0, 53), &Mpfr::from_f64(0.25, 53)),
+             Ball::with_radius(&Mpfr::from_f64(-10.0, 53), &Mpfr::from_f64(1e-10, 53))]
+    }
+
+    // The ends and the midpoint
+    fn points(x: &Ball) -> Vec<Mpfr> {
+        let lo = x.mid().apply2(super::super::mpfr_sub, x.rad(), REF, MPFR_RNDN);
+        let hi = x.mid().apply2(super::super::mpfr_add, x.rad(), REF, MPFR_RNDN);
+        vec![lo, x.mid().clone(), hi]
+    }
+
+    fn contains1<G: Fn(&Ball) -> Option<Ball>>(f: Fn1, g: G) {
+        for x in balls().iter() {
+            if let Some(r) = g(x) {
+                for p in points(x).iter() {
+                    assert!(r.contains(&p.apply(f, REF, MPFR_RNDN)));
+                }
+            }
+        }
+    }
+
+    fn contains2<G: Fn(&Ball, &Ball) -> Option<Ball>>(f: Fn2, g: G) {
+        for x in balls().iter() {
+            for y in balls().iter() {
+                if let Some(r) = g(x, y) {
+                    for p in points(x).iter() {
+                        for q in points(y).iter() {
+                            assert!(r.contains(&p.apply2(f, q, REF, MPFR_RNDN)));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn arithmetic_contains_exact_results() {
+        contains2(super::super::mpfr_add, |x: &Ball, y: &Ball| Some(x.add(y)));
+        contains2(super::super::mpfr_sub, |x: &Ball, y: &Ball| Some(x.sub(y)));
+        contains2(super::super::mpfr_mul, |x: &Ball, y: &Ball| Some(x.mul(y)));
+        contains2(super::super::mpfr_div, |x: &Ball, y: &Ball| x.div(y).ok());
+    }
+
+    #[test]
+    fn functions_contain_exact_results() {
+        contains1(super::super::mpfr_exp, |x: &Ball| Some(x.exp()));
+        contains1(super::super::mpfr_log, |x: &Ball| x.log().ok());
+        contains1(super::super::mpfr_sin, |x: &Ball| Some(x.sin()));
+        contains1(super::super::mpfr_cos, |x: &Ball| Some(x.cos()));
+    }
+
+    #[test]
+    fn domain_errors() {
+        let zero = Ball::with_radius(&Mpfr::from_f64(0.5, 53), &Mpfr::from_f64(1.0, 53));
+        assert!(Ball::from_f64(1.0, 53).div(&zero).is_err());
+        assert!(zero.log().is_err());
+        assert!(Ball::from_f64(-1.0, 53).log().is_err());
+    }
+
+    #[test]
+    fn accurate_bits() {
+        assert_eq!(Ball::from_f64(1.5, 53).accurate_bits(), 53);
+        let third = Ball::from_f64(1.0, 53).div(&Ball::from_f64(3.0, 53)).ok().unwrap();
+        assert!(third.accurate_bits() >= 52);
+    }
+
+    // x * y with emin = -100, where the smallest positive number is 2^-101
+    fn mul_near_underflow(x: &Mpfr, y: &Mpfr) -> Ball {
+        let emin = context::emin();
+        context::set_emin(-100);
+        let r = Ball::exact(x).mul(&Ball::exact(y));
+        context::set_emin(emin);
+        r
+    }
+
+    #[test]
+    fn underflow() {
+        let pow2 = |e: mpfr_exp_t| {
+            let mut r = Mpfr::new(53);
+            unsafe { super::super::mpfr_set_ui_2exp(r.as_mut_ptr(), 1, e, MPFR_RNDN) };
+            r
+        };
+        let three = Mpfr::from_ui(3, 53);
+        // 3 * 2^-103 rounds up to 2^-101
+        let x = three.apply2(super::super::mpfr_mul, &pow2(-52), 53, MPFR_RNDN);
+        let y = pow2(-51);
+        let exact = x.exact_mul(&y);
+        let r = mul_near_underflow(&x, &y);
+        assert_eq!(r.mid().to_f64(MPFR_RNDN), 2.0f64.powi(-101));
+        assert!(r.contains(&exact));
+        // 2^-104 rounds to zero
+        let (x, y) = (pow2(-52), pow2(-52));
+        let r = mul_near_underflow(&x, &y);
+        assert_eq!(r.mid().sgn(), 0);
+        assert!(r.contains(&x.exact_mul(&y)));
+    }
+}
@@ -0,0 +1,56 @@
+#![allow(unstable)]
+
+extern crate test;
+extern crate "mpfr-sys" as mpfr;
+
+use test::{Bencher, black_box};
+use mpfr::float::Mpfr;
+
+// Calls of the out-of-line accessors against the inline Rust versions. Normally every call
+// to the former crosses into libmpfr; with the lto feature and a cross-language LTO build
+//
+//     RUSTFLAGS="-Clinker-plugin-lto -Clinker=clang -Clink-arg=-fuse-ld=lld" \
+//         cargo bench --features lto --bench accessors
+//
+// they are inlined and should run as fast as the inline versions. Compare with the
+// numbers of a plain `cargo bench --bench accessors`.
+
+const N: usize = 1000;
+
+fn values() -> Vec<Mpfr> {
+    (0..N).map(|i| Mpfr::from_f64(i as f64 - 500.0, 53)).collect()
+}
+
+#[bench]
+fn extern_accessors(b: &mut Bencher) {
+    let xs = values();
+    b.iter(|| {
+        let mut acc = 0i64;
+        for x in xs.iter() {
+            let p = black_box(x.as_ptr());
+            unsafe {
+                acc += mpfr::mpfr_get_prec(p) as i64;
+                acc += mpfr::mpfr_sgn(p) as i64;
+                acc += mpfr::mpfr_nan_p(p) as i64;
+            }
+        }
+        acc
+    });
+}
+
+#[bench]
+fn inline_accessors(b: &mut Bencher) {
+    let xs = values();
+    b.iter(|| {
+        let mut acc = 0i64;
+        for x in xs.iter() {
+            let p = black_box(x.as_ptr());
+            unsafe {
+                acc += mpfr::inline::mpfr_get_prec(p) as i64;
+                acc += mpfr::inline::mpfr_sgn(p) as i64;
+                acc += mpfr::inline::mpfr_nan_p(p) as i64;
+            }
+        }
+        acc
+    });
+}
@@ -0,0 +1,45 @@
+#![allow(unstable)]
+
+extern crate test;
+extern crate libc;
+extern crate "mpfr-sys" as mpfr;
+
+use test::{Bencher, black_box};
+use libc::c_ulong;
+use mpfr::MPFR_RNDN;
+use mpfr::arena::Arena;
+use mpfr::float::Mpfr;
+
+// Creating, setting and releasing N values of 256 bits, from an arena against a separate
+// mpfr_init2/mpfr_clear for each value
+//
+//     cargo bench --bench arena
+
+const N: usize = 1000;
+const PREC: mpfr::mpfr_prec_t = 256;
+
+#[bench]
+fn arena_values(b: &mut Bencher) {
+    let mut arena = Arena::new(PREC, N);
+    b.iter(|| {
+        {
+            let mut values: Vec<_> = (0..N).map(|_| arena.alloc().unwrap()).collect();
+            for (i, x) in values.iter_mut().enumerate() {
+                unsafe { mpfr::mpfr_set_ui(x.as_mut_ptr(), i as c_ulong, MPFR_RNDN) };
+            }
+            black_box(&values);
+        }
+        arena.reset();
+    });
+}
+
+#[bench]
+fn init2_clear_values(b: &mut Bencher) {
+    b.iter(|| {
+        let mut values: Vec<_> = (0..N).map(|_| Mpfr::new(PREC)).collect();
+        for (i, x) in values.iter_mut().enumerate() {
+            unsafe { mpfr::mpfr_set_ui(x.as_mut_ptr(), i as c_ulong, MPFR_RNDN) };
+        }
+        black_box(&values);
+    });
+}
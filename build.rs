@@ -1,7 +1,7 @@
 #![allow(unstable)]
 
 use std::os;
-use std::io::{self, fs, Command, BufReader};
+use std::io::{self, fs, Command, BufReader, File};
 use std::io::process::InheritFd;
 use std::io::fs::PathExtensions;
 
@@ -39,13 +39,25 @@ fn check_library(name: &str) -> bool {
 }
 
 fn main() {
-    // MPFR does not support pkg-config :(
-    // Try to guess its presence manually
-    if check_library(MPFR_NAME) {
-        println!("cargo:rustc-flags=-l mpfr");
-        return;
+    let target = os::getenv("TARGET").unwrap();
+    let host = os::getenv("HOST").unwrap();
+
+    // Telling cargo about any input makes it track only those, see also feature()
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=abi_probe.c");
+    for var in ["CC", "CFLAGS", "LDFLAGS", "MPFR_SYS_CACHE_DIR"].iter() {
+        println!("cargo:rerun-if-env-changed={}", var);
     }
 
+    // MPFR does not support pkg-config :(
+    // Try to guess its presence manually; libraries installed on the host
+    // are of no use when we are cross-compiling or linking against another libc
+    // Instrumented or LTO-enabled library can only be obtained by building it ourselves
+    let same_libc = target_libc(&*host) == target_libc(&*target);
+    let custom = feature("ASAN") || feature("UBSAN") || feature("MPFR_DEBUG") ||
+                 feature("MPFR_ASSERT") || feature("LTO");
+    let config = BuildConfig::new(&*host, &*target);
+
     // Bind some useful paths
 
     let project_src_root = Path::new(os::getenv("CARGO_MANIFEST_DIR").unwrap());
@@ -53,77 +65,306 @@ fn main() {
 
     let out_dir = Path::new(os::getenv("OUT_DIR").unwrap());
 
+    if !is_cross(&*host, &*target) && same_libc && !custom && check_library(MPFR_NAME) {
+        probe_abi(&config, &project_src_root, &out_dir, None);
+        println!("cargo:rustc-flags=-l mpfr");
+        return;
+    }
+
     let mpfr_build_dir = out_dir.join("build");
 
     let mpfr_out_dir = out_dir.join("out");
-    let mpfr_out_lib_dir = mpfr_out_dir.join("lib");
-    let mpfr_out_include_dir = mpfr_out_dir.join("include");
 
-    // Do not rebuild libmpfr if it had already been built
-    
-    if !(mpfr_out_lib_dir.exists() && mpfr_out_lib_dir.join("libmpfr.a").exists() &&
-         mpfr_out_include_dir.exists() && mpfr_out_include_dir.join("mpfr.h").exists()) {
-        run_build(&mpfr_src_root, &mpfr_build_dir, 
-                  &mpfr_out_dir, &mpfr_out_lib_dir, &mpfr_out_include_dir);
-    }
+    let fingerprint = config.fingerprint();
+
+    // Do not rebuild libmpfr if it had already been built with the same configuration,
+    // either in this OUT_DIR or in the shared cache directory if one is configured
+
+    let mpfr_dir = match os::getenv("MPFR_SYS_CACHE_DIR") {
+        Some(cache_root) => {
+            let key = format!("{}-{}-{:016x}", MPFR_VERSION, target, fnv1a(fingerprint.as_bytes()));
+            let cache_dir = Path::new(cache_root).join(key);
+            if !is_up_to_date(&cache_dir, &*fingerprint) {
+                if !is_up_to_date(&mpfr_out_dir, &*fingerprint) {
+                    run_build(&config, &mpfr_src_root, &mpfr_build_dir, &mpfr_out_dir, &*fingerprint);
+                }
+                publish(&mpfr_out_dir, &cache_dir);
+            }
+            // An existing entry which does not match is left alone and the local build used
+            if is_up_to_date(&cache_dir, &*fingerprint) { cache_dir } else { mpfr_out_dir }
+        }
+        None => {
+            if !is_up_to_date(&mpfr_out_dir, &*fingerprint) {
+                run_build(&config, &mpfr_src_root, &mpfr_build_dir, &mpfr_out_dir, &*fingerprint);
+            }
+            mpfr_out_dir
+        }
+    };
+    let mpfr_lib_dir = mpfr_dir.join("lib");
+    let mpfr_include_dir = mpfr_dir.join("include");
+
+    // Make sure we are not going to link an archive built for some other machine
+    verify_archive(&mpfr_lib_dir.join("libmpfr.a"), &*target);
+
+    // Select Rust types matching the header and check the structure layout
+    probe_abi(&config, &project_src_root, &out_dir, Some(&mpfr_include_dir));
 
     // TODO: Regenerate and update source file if we have bindgen, otherwise copy prebuilt source
 
     // Emit cargo config
-    emit_cargo_config(&mpfr_out_lib_dir, &mpfr_out_include_dir);
+    emit_cargo_config(&mpfr_lib_dir, &mpfr_include_dir, &config);
 }
 
-fn run_build(mpfr_src_root: &Path,
-             mpfr_build_dir: &Path,
-             mpfr_out_dir: &Path,
-             mpfr_out_lib_dir: &Path,
-             mpfr_out_include_dir: &Path) {
-    // let windows = target.contains("windows") || target.contains("mingw");
-    //
-    let target = os::getenv("TARGET").unwrap();
+// Everything which affects the contents of the built library
+struct BuildConfig {
+    host: String,
+    target: String,
+    cc: Option<String>,
+    ar: Option<String>,
+    ranlib: Option<String>,
+    cflags: String,
+    ldflags: String,
+    gmp_include: Option<String>,
+    gmp_libdir: Option<String>,
+    config_opts: Vec<String>,
+    // Sanitizer runtimes in link order, and the directories of clang's ones
+    runtime_libs: Vec<String>,
+    runtime_dirs: Vec<String>
+}
+
+impl BuildConfig {
+    fn new(host: &str, target: &str) -> BuildConfig {
+        // let windows = target.contains("windows") || target.contains("mingw");
+        //
+        // Cross-language LTO requires LLVM bitcode, so LLVM tools are used unless set explicitly
+        let lto = feature("LTO");
+        let (cc, ar, ranlib) = if lto {
+            (Some(env_tool("CC", host, target).unwrap_or("clang".to_string())),
+             Some(env_tool("AR", host, target).unwrap_or("llvm-ar".to_string())),
+             Some(env_tool("RANLIB", host, target).unwrap_or("llvm-ranlib".to_string())))
+        } else {
+            let cc = tool_from_env("CC", host, target, "gcc").or_else(|| {
+                // Native musl builds on glibc hosts usually go through the musl-gcc wrapper
+                if target_libc(target) == "musl" && target_libc(host) != "musl" {
+                    Some("musl-gcc".to_string())
+                } else {
+                    None
+                }
+            });
+            (cc, tool_from_env("AR", host, target, "ar"), tool_from_env("RANLIB", host, target, "ranlib"))
+        };
+
+        let mut ldflags = os::getenv("LDFLAGS").unwrap_or(String::new());
+
+        let mut cflags = os::getenv("CFLAGS").unwrap_or(String::new());
+        cflags.push_str(" -ffunction-sections -fdata-sections");
+        if target.contains("i686") {
+            cflags.push_str(" -m32");
+        } else if target.contains("x86_64") {
+            cflags.push_str(" -m64");
+        }
+        // musl executables are linked statically, possibly as static PIE, so PIC is needed everywhere
+        if !target.contains("i686") || target_libc(target) == "musl" {
+            cflags.push_str(" -fPIC");
+        }
+
+        let mut config_opts = vec![
+            "--enable-shared=no".to_string(), // TODO: why?
+            format!("--host={}", autoconf_triple(target)),
+            format!("--build={}", autoconf_triple(host))
+        ];
+
+        // Sanitizers need the flags at link time too since configure links test programs.
+        // rustc does not link through the C compiler driver with -fsanitize, so the runtime
+        // libraries are named explicitly, as the compiler names them; clang's runtime is
+        // linked dynamically like gcc's so that ASan is first in the initial library list
+        let mut runtime_libs = vec![];
+        let mut runtime_dirs = vec![];
+        if feature("MPFR_DEBUG") {
+            cflags.push_str(" -O0 -g");
+        }
+        if feature("ASAN") || feature("UBSAN") {
+            let driver = cc.clone().unwrap_or("cc".to_string());
+            let clang = is_clang(&*driver);
+            if clang {
+                ldflags.push_str(" -shared-libsan");
+            }
+            // ASan runtime of clang handles UBSan checks too and must not be linked twice
+            let mut sanitizers = vec![];
+            if feature("ASAN") {
+                cflags.push_str(" -fsanitize=address -fno-omit-frame-pointer");
+                ldflags.push_str(" -fsanitize=address");
+                sanitizers.push("asan");
+            }
+            if feature("UBSAN") {
+                cflags.push_str(" -fsanitize=undefined");
+                ldflags.push_str(" -fsanitize=undefined");
+                if !clang {
+                    sanitizers.push("ubsan");
+                } else if !feature("ASAN") {
+                    sanitizers.push("ubsan_standalone");
+                }
+            }
+            for &name in sanitizers.iter() {
+                if clang {
+                    let (dir, lib) = clang_runtime(&*driver, name, target);
+                    if !runtime_dirs.contains(&dir) {
+                        runtime_dirs.push(dir);
+                    }
+                    runtime_libs.push(lib);
+                } else {
+                    runtime_libs.push(name.to_string());
+                }
+            }
+        }
+        if feature("MPFR_ASSERT") {
+            config_opts.push("--enable-assert".to_string());
+        }
+
+        // ThinLTO objects are only linkable by an LTO-aware linker, which configure needs
+        // for its test programs; the final Rust binary has to be linked by clang with lld and
+        // built with -Clinker-plugin-lto for MPFR functions to be inlined into Rust code
+        if lto {
+            cflags.push_str(" -flto=thin");
+            ldflags.push_str(" -flto=thin -fuse-ld=lld");
+            if is_cross(host, target) {
+                cflags.push_str(&*format!(" --target={}", target));
+                ldflags.push_str(&*format!(" --target={}", target));
+            }
+        }
+
+        let gmp_include = os::getenv("DEP_GMP_INCLUDE");
+        let gmp_libdir = os::getenv("DEP_GMP_LIBDIR");
+
+        // A system GMP is built against the host libc and cannot be linked into a musl executable
+        if target_libc(target) == "musl" && (gmp_include.is_none() || gmp_libdir.is_none()) {
+            panic!("building for {} requires GMP built by gmp-sys for the same target, \
+                    but DEP_GMP_INCLUDE/DEP_GMP_LIBDIR are not set", target);
+        }
 
-    let mut ldflags = os::getenv("LDFLAGS").unwrap_or(String::new());
-    if let Some(gmp_libdir) = os::getenv("DEP_GMP_LIBDIR") {
-        ldflags.push_str(" -L");
-        ldflags.push_str(&*gmp_libdir);
+        BuildConfig {
+            host: host.to_string(),
+            target: target.to_string(),
+            cc: cc,
+            ar: ar,
+            ranlib: ranlib,
+            cflags: cflags,
+            ldflags: ldflags,
+            gmp_include: gmp_include,
+            gmp_libdir: gmp_libdir,
+            config_opts: config_opts,
+            runtime_libs: runtime_libs,
+            runtime_dirs: runtime_dirs
+        }
+    }
+
+    fn cflags(&self) -> String {
+        let mut cflags = self.cflags.clone();
+        if let Some(ref gmp_include) = self.gmp_include {
+            cflags.push_str(" -I");
+            cflags.push_str(&**gmp_include);
+        }
+        cflags
+    }
+
+    fn ldflags(&self) -> String {
+        let mut ldflags = self.ldflags.clone();
+        if let Some(ref gmp_libdir) = self.gmp_libdir {
+            ldflags.push_str(" -L");
+            ldflags.push_str(&**gmp_libdir);
+        }
+        ldflags
+    }
+
+    // GMP directories live in gmp-sys OUT_DIR which differs between workspaces and
+    // profiles, so the fingerprint includes the contents of gmp.h instead of its path
+    fn fingerprint(&self) -> String {
+        let gmp_h = self.gmp_include.as_ref()
+            .and_then(|dir| File::open(&Path::new(&**dir).join("gmp.h")).read_to_end().ok())
+            .map(|contents| format!("{:016x}", fnv1a(&*contents)))
+            .unwrap_or("system".to_string());
+        let none = "default".to_string();
+        format!("mpfr {}\nhost {}\ntarget {}\ncc {}\nar {}\nranlib {}\ncflags {}\nldflags {}\n\
+                 gmp.h {}\nconfigure {}\n",
+                MPFR_VERSION, self.host, self.target,
+                self.cc.as_ref().unwrap_or(&none), self.ar.as_ref().unwrap_or(&none),
+                self.ranlib.as_ref().unwrap_or(&none),
+                self.cflags, self.ldflags, gmp_h, self.config_opts.connect(" "))
+    }
+}
+
+fn is_up_to_date(mpfr_dir: &Path, fingerprint: &str) -> bool {
+    mpfr_dir.join("lib/libmpfr.a").exists() && mpfr_dir.join("include/mpfr.h").exists() &&
+        File::open(&mpfr_dir.join("fingerprint")).read_to_string()
+            .map(|s| s == fingerprint).unwrap_or(false)
+}
+
+// Copies built library to the shared cache; the copy is made under a temporary name and
+// then renamed so concurrent builds never see a partially written directory. An existing
+// entry is never replaced, as another build may be linking against it.
+fn publish(mpfr_out_dir: &Path, cache_dir: &Path) {
+    if cache_dir.exists() {
+        return;
     }
+    let out_dir = os::getenv("OUT_DIR").unwrap();
+    let tmp_dir = cache_dir.with_filename(format!("{}.tmp-{:016x}",
+                                                  cache_dir.filename_str().unwrap(),
+                                                  fnv1a(out_dir.as_bytes())));
+    let _ = fs::rmdir_recursive(&tmp_dir);
+    for sub in ["lib", "include"].iter() {
+        let dst = tmp_dir.join(*sub);
+        fs::mkdir_recursive(&dst, io::USER_DIR).unwrap();
+        for src in fs::readdir(&mpfr_out_dir.join(*sub)).unwrap().iter() {
+            fs::copy(src, &dst.join(src.filename().unwrap())).unwrap();
+        }
+    }
+    fs::copy(&mpfr_out_dir.join("fingerprint"), &tmp_dir.join("fingerprint")).unwrap();
 
-    let mut cflags = os::getenv("CFLAGS").unwrap_or(String::new());
-    cflags.push_str(" -ffunction-sections -fdata-sections");
-    if target.contains("i686") {
-        cflags.push_str(" -m32");
-    } else if target.as_slice().contains("x86_64") {
-        cflags.push_str(" -m64");
+    // Somebody else may have published the same build in the meantime, which is fine;
+    // renaming onto their non-empty directory fails and our copy is discarded
+    if cache_dir.exists() || fs::rename(&tmp_dir, cache_dir).is_err() {
+        let _ = fs::rmdir_recursive(&tmp_dir);
     }
-    if !target.contains("i686") {
-        cflags.push_str(" -fPIC");
-    }    
-    if let Some(gmp_include) = os::getenv("DEP_GMP_INCLUDE") {
-        cflags.push_str(" -I");
-        cflags.push_str(&*gmp_include);
+}
+
+fn fnv1a(data: &[u8]) -> u64 {
+    let mut hash = 0xcbf29ce484222325u64;
+    for &b in data.iter() {
+        hash = (hash ^ b as u64) * 0x100000001b3;
     }
+    hash
+}
+
+fn run_build(config: &BuildConfig,
+             mpfr_src_root: &Path,
+             mpfr_build_dir: &Path,
+             mpfr_out_dir: &Path,
+             fingerprint: &str) {
+    let mpfr_out_lib_dir = mpfr_out_dir.join("lib");
+    let mpfr_out_include_dir = mpfr_out_dir.join("include");
 
     let _ = fs::rmdir_recursive(mpfr_build_dir);
     let _ = fs::rmdir_recursive(mpfr_out_dir);
 
-    let _ = fs::mkdir_recursive(mpfr_out_lib_dir, io::USER_DIR);
-    let _ = fs::mkdir_recursive(mpfr_out_include_dir, io::USER_DIR);
+    let _ = fs::mkdir_recursive(&mpfr_out_lib_dir, io::USER_DIR);
+    let _ = fs::mkdir_recursive(&mpfr_out_include_dir, io::USER_DIR);
     fs::mkdir(mpfr_build_dir, io::USER_DIR).unwrap();
 
-    let config_opts = vec![
-        "--enable-shared=no".to_string() // TODO: why?
-    ];
+    let ldflags = config.ldflags();
 
     // Run configure
     run(Command::new("sh")
-                .env("CFLAGS", cflags)
-                .tap_mut(|c| if !ldflags.is_empty() { c.env("LDFLAGS", &*ldflags); })
+                .env("CFLAGS", config.cflags())
+                .tap_mut(|c| if !ldflags.trim().is_empty() { c.env("LDFLAGS", &*ldflags); })
+                .tap_mut(|c| if let Some(ref cc) = config.cc { c.env("CC", &**cc); })
+                .tap_mut(|c| if let Some(ref ar) = config.ar { c.env("AR", &**ar); })
+                .tap_mut(|c| if let Some(ref ranlib) = config.ranlib { c.env("RANLIB", &**ranlib); })
                 .cwd(mpfr_build_dir)
                 .arg("-c")
                 .arg(format!(
                     "{} {}", 
                     mpfr_src_root.join("configure").display(),
-                    config_opts.connect(" ")
+                    config.config_opts.connect(" ")
                 ).replace("C:\\", "/c/").replace("\\", "/")));
 
     // Run make
@@ -143,14 +384,489 @@ fn run_build(mpfr_src_root: &Path,
     // Copy the single include file
     fs::copy(&mpfr_src_root.join("src/mpfr.h"), &mpfr_out_include_dir.join("mpfr.h")).unwrap();
     fs::copy(&mpfr_src_root.join("src/mpf2mpfr.h"), &mpfr_out_include_dir.join("mpf2mpfr.h")).unwrap();
+
+    // The fingerprint is written last so an interrupted build is never considered complete
+    File::create(&mpfr_out_dir.join("fingerprint")).write_str(fingerprint).unwrap();
 }
 
-fn emit_cargo_config(lib_dir: &Path, include_dir: &Path) {
+// mpfr_prec_t and mpfr_exp_t depend on _MPFR_PREC_FORMAT and _MPFR_EXP_FORMAT macros, which
+// the library could have been configured with. Rust definitions of these types are generated
+// into OUT_DIR/mpfr_types.rs according to the header, and the layout of __mpfr_struct as
+// reported by the C compiler is checked against the Rust declaration of lib.rs with these
+// types. Version macros and other literal constants go to OUT_DIR/mpfr_consts.rs. The probe
+// is only compiled, so all of this works the same when cross-compiling.
+fn probe_abi(config: &BuildConfig, project_src_root: &Path, out_dir: &Path,
+             include_dir: Option<&Path>) {
+    let src = project_src_root.join("abi_probe.c");
+    let obj = out_dir.join("abi_probe.o");
+    let cc = config.cc.clone().unwrap_or("cc".to_string());
+    let includes = include_dir.map(|d| format!("-I{}", d.display())).unwrap_or(String::new());
+    // Bitcode would hide the values from us
+    let no_lto = if config.cflags.contains("-flto") { "-fno-lto" } else { "" };
+
+    run(Command::new("sh").arg("-c").arg(format!(
+        "{} {} {} {} -c {} -o {}", cc, config.cflags(), includes, no_lto,
+        src.display(), obj.display()
+    ).replace("C:\\", "/c/").replace("\\", "/")));
+    let values = parse_probe_object(&*File::open(&obj).read_to_end().unwrap());
+    let raw_value = |key: &str| match values.iter().find(|&&(ref k, _)| &**k == key) {
+        Some(&(_, ref v)) => v.clone(),
+        None => panic!("{} is not reported by {}", key, src.display())
+    };
+    let value = |key: &str| match parse_decimal(raw_value(key).as_bytes()) {
+        Some(v) => v,
+        None => panic!("{} reported by {} is not a number", key, src.display())
+    };
+
+    // C type name and its Rust counterpart
+    let int_type = |format: usize, signed: bool| match (format, signed) {
+        (1, true) => ("short", "::libc::c_short"),
+        (1, false) => ("short", "::libc::c_ushort"),
+        (2, true) => ("int", "::libc::c_int"),
+        (2, false) => ("int", "::libc::c_uint"),
+        (3, true) => ("long", "::libc::c_long"),
+        (3, false) => ("long", "::libc::c_ulong"),
+        (4, true) => ("intmax_t", "::libc::intmax_t"),
+        (4, false) => ("intmax_t", "::libc::uintmax_t"),
+        (f, _) => panic!("unsupported MPFR type format {}", f)
+    };
+    let prec_format = value("prec_format");
+    let exp_format = value("exp_format");
+    let (prec_c, prec_t) = int_type(prec_format, true);
+    let (_, uprec_t) = int_type(prec_format, false);
+    let (exp_c, exp_t) = int_type(exp_format, true);
+    let (_, uexp_t) = int_type(exp_format, false);
+
+    // Lay out Struct_Unnamed2 of lib.rs as #[repr(C)] does with the libc types of the target
+    // and compare with the C structure
+    let target = &*config.target;
+    let fields = [("_mpfr_prec", rust_layout(prec_c, target)),
+                  ("_mpfr_sign", rust_layout("int", target)),
+                  ("_mpfr_exp", rust_layout(exp_c, target)),
+                  ("_mpfr_d", rust_layout("pointer", target))];
+    let mut mismatches = vec![];
+    for &(c_type, rust_type) in [("mpfr_prec_t", prec_c), ("mpfr_sign_t", "int"),
+                                 ("mpfr_exp_t", exp_c)].iter() {
+        let (size, _) = rust_layout(rust_type, target);
+        if value(&*format!("sizeof_{}", c_type)) != size {
+            mismatches.push(format!("{} has size {} instead of {}",
+                                    c_type, value(&*format!("sizeof_{}", c_type)), size));
+        }
+    }
+    let mut offset = 0;
+    let mut max_align = 1;
+    for &(name, (size, align)) in fields.iter() {
+        offset = (offset + align - 1) / align * align;
+        max_align = if align > max_align { align } else { max_align };
+        let c_offset = value(&*format!("offsetof_{}", name));
+        if c_offset != offset {
+            mismatches.push(format!("{} is at offset {} instead of {}", name, c_offset, offset));
+        }
+        offset += size;
+    }
+    let size = (offset + max_align - 1) / max_align * max_align;
+    if value("sizeof___mpfr_struct") != size {
+        mismatches.push(format!("__mpfr_struct has size {} instead of {}",
+                                value("sizeof___mpfr_struct"), size));
+    }
+    if !mismatches.is_empty() {
+        panic!("__mpfr_struct layout in mpfr.h does not match Rust declarations for {}: {}",
+               target, mismatches.connect("; "));
+    }
+
+    // The C layout, checked against the compiled Rust structure by the tests of lib.rs
+    let layout = format!(
+        "const C_SIZE: usize = {};\nconst C_OFFSETS: [usize; 4] = [{}, {}, {}, {}];\n",
+        value("sizeof___mpfr_struct"), value("offsetof__mpfr_prec"),
+        value("offsetof__mpfr_sign"), value("offsetof__mpfr_exp"), value("offsetof__mpfr_d")
+    );
+    File::create(&out_dir.join("mpfr_layout.rs")).write_str(&*layout).unwrap();
+
+    let types = format!(
+        "pub type mpfr_prec_t = {};\npub type mpfr_uprec_t = {};\n\
+         pub type mpfr_sign_t = ::libc::c_int;\n\
+         pub type mpfr_exp_t = {};\npub type mpfr_uexp_t = {};\n",
+        prec_t, uprec_t, exp_t, uexp_t
+    );
+    File::create(&out_dir.join("mpfr_types.rs")).write_str(&*types).unwrap();
+
+    // Version string is reported with quotes
+    let version_string = raw_value("version_string");
+    if !(version_string.len() >= 2 && version_string.starts_with("\"") &&
+         version_string.ends_with("\"")) {
+        panic!("MPFR_VERSION_STRING reported by {} is not a string", src.display());
+    }
+    let consts = format!(
+        "pub const MPFR_VERSION_MAJOR: ::libc::c_int = {};\n\
+         pub const MPFR_VERSION_MINOR: ::libc::c_int = {};\n\
+         pub const MPFR_VERSION_PATCHLEVEL: ::libc::c_int = {};\n\
+         pub const MPFR_VERSION_STRING: &'static str = {};\n\
+         pub const MPFR_PREC_MIN: mpfr_prec_t = {};\n\
+         pub const MPFR_PREC_MAX: mpfr_prec_t = {};\n\
+         pub const MPFR_EMIN_DEFAULT: mpfr_exp_t = {};\n\
+         pub const MPFR_EMAX_DEFAULT: mpfr_exp_t = {};\n\
+         pub const __MPFR_EXP_MAX: mpfr_exp_t = {};\n\
+         pub const __MPFR_EXP_NAN: mpfr_exp_t = {};\n\
+         pub const __MPFR_EXP_ZERO: mpfr_exp_t = {};\n\
+         pub const __MPFR_EXP_INF: mpfr_exp_t = {};\n",
+        value("version_major"), value("version_minor"), value("version_patchlevel"),
+        version_string, value("prec_min"), raw_value("prec_max"), raw_value("emin_default"),
+        raw_value("emax_default"), raw_value("exp_max"), raw_value("exp_nan"),
+        raw_value("exp_zero"), raw_value("exp_inf")
+    );
+    File::create(&out_dir.join("mpfr_consts.rs")).write_str(&*consts).unwrap();
+}
+
+// Size and alignment on the target of the Rust type used for a C type, as defined by the
+// libc crate: long follows the pointer width except on Windows, and 64-bit integers are
+// aligned on 4 bytes on 32-bit x86 except on Windows
+fn rust_layout(c_type: &str, target: &str) -> (usize, usize) {
+    let arch = target.split('-').next().unwrap();
+    let pointer = if arch.contains("64") { 8 } else { 4 };
+    let windows = target.contains("-windows");
+    let size = match c_type {
+        "short" => 2,
+        "int" => 4,
+        "long" => if windows { 4 } else { pointer },
+        "intmax_t" => 8,
+        "pointer" => pointer,
+        t => panic!("unknown C type {}", t)
+    };
+    let align = if size == 8 && is_x86(target) && arch != "x86_64" && !windows { 4 } else { size };
+    (size, align)
+}
+
+// Extracts the `MPFR_PROBE name value` lines from the compiled probe; values are either
+// signed numbers of 20 digits, reported without the sign and leading zeros when positive,
+// or strings with their quotes
+fn parse_probe_object(data: &[u8]) -> Vec<(String, String)> {
+    let marker = b"MPFR_PROBE ";
+    let mut result = vec![];
+    let mut pos = 0;
+    while pos < data.len() {
+        if !data[pos..].starts_with(marker) {
+            pos += 1;
+            continue;
+        }
+        pos += marker.len();
+        let end = match data[pos..].iter().position(|&b| b == b'\n') {
+            Some(n) => pos + n,
+            None => break
+        };
+        let line = String::from_utf8_lossy(&data[pos..end]).into_owned();
+        if let Some(i) = line.find(' ') {
+            let (name, value) = (line[..i].to_string(), line[i + 1..].to_string());
+            let value = if value.starts_with("+") || value.starts_with("-") {
+                let digits = value[1..].trim_left_matches('0');
+                let digits = if digits.is_empty() { "0" } else { digits };
+                if value.starts_with("-") && digits != "0" {
+                    format!("-{}", digits)
+                } else {
+                    digits.to_string()
+                }
+            } else {
+                value
+            };
+            result.push((name, value));
+        }
+        pos = end;
+    }
+    result
+}
+
+fn emit_cargo_config(lib_dir: &Path, include_dir: &Path, config: &BuildConfig) {
+    // Sanitizer runtimes go first: ASan refuses to start if it is not the first library
+    for dir in config.runtime_dirs.iter() {
+        println!("cargo:rustc-flags=-L {}", dir);
+    }
+    for lib in config.runtime_libs.iter() {
+        println!("cargo:rustc-flags=-l {}", lib);
+    }
     println!("cargo:rustc-flags=-L {} -l mpfr:static", lib_dir.display());
     println!("cargo:libdir={}", lib_dir.display());
     println!("cargo:include={}", include_dir.display());
 }
 
+// Converts a Rust target triple to the one understood by MPFR's config.sub
+fn autoconf_triple(triple: &str) -> String {
+    let arch = triple.split('-').next().unwrap();
+    if target_libc(triple) == "musl" {
+        // config.sub shipped with MPFR 3.1.2 predates musl; the libc does not matter
+        // to configure as long as the right compiler is used
+        triple.replace("-musl", "-gnu")
+    } else if triple.ends_with("-windows-gnu") {
+        format!("{}-w64-mingw32", arch)
+    } else if triple.ends_with("-linux-androideabi") {
+        "arm-linux-androideabi".to_string()
+    } else if triple.ends_with("-apple-ios") {
+        format!("{}-apple-darwin", if arch == "aarch64" { "arm" } else { arch })
+    } else {
+        triple.to_string()
+    }
+}
+
+// Prefix of cross toolchain binaries, e.g. `aarch64-linux-musl` for `aarch64-linux-musl-gcc`;
+// Linux toolchains are named without the vendor, e.g. `aarch64-linux-gnu-gcc`
+fn toolchain_prefix(triple: &str) -> String {
+    if target_libc(triple) == "musl" {
+        format!("{}-linux-musl", triple.split('-').next().unwrap())
+    } else {
+        autoconf_triple(triple).replace("-unknown-linux-", "-linux-")
+    }
+}
+
+fn target_libc(triple: &str) -> &'static str {
+    if triple.contains("-musl") { "musl" }
+    else if triple.contains("-windows") { "msvcrt" }
+    else if triple.contains("-android") { "bionic" }
+    else if triple.contains("-linux-gnu") { "glibc" }
+    else { "" }
+}
+
+fn is_x86(triple: &str) -> bool {
+    let arch = triple.split('-').next().unwrap();
+    arch == "x86_64" || (arch.starts_with("i") && arch.ends_with("86"))
+}
+
+// Building for a target which differs from the host only in x86 bitness or in libc is not
+// really cross-compilation: the host compiler handles it with -m32/-m64 flags or a wrapper
+// like musl-gcc
+fn is_cross(host: &str, target: &str) -> bool {
+    if host == target { return false; }
+    let rest = |t: &str| t.find('-').map(|i| t[i..].replace("-musl", "-gnu"));
+    !(is_x86(host) && is_x86(target) && rest(host) == rest(target))
+}
+
+// Looks for a tool in the environment following the conventions of the gcc crate:
+// `CC_x86_64_unknown_linux_gnu`, then `TARGET_CC`, then plain `CC` for native builds.
+// When cross-compiling the tool defaults to the prefixed one, e.g. `aarch64-unknown-linux-gnu-gcc`;
+// for native builds configure is left to find the tool by itself.
+fn tool_from_env(name: &str, host: &str, target: &str, default: &str) -> Option<String> {
+    env_tool(name, host, target).or_else(|| if is_cross(host, target) {
+        Some(format!("{}-{}", toolchain_prefix(target), default))
+    } else {
+        None
+    })
+}
+
+fn env_tool(name: &str, host: &str, target: &str) -> Option<String> {
+    os::getenv(&*format!("{}_{}", name, target.replace("-", "_")))
+        .or_else(|| os::getenv(&*format!("TARGET_{}", name)))
+        .or_else(|| if is_cross(host, target) { None } else { os::getenv(name) })
+}
+
+#[derive(Copy, PartialEq)]
+enum Machine { X86, X86_64, Arm, Aarch64, Mips, Mips64, PowerPc, PowerPc64 }
+
+impl Machine {
+    fn from_triple(triple: &str) -> Option<Machine> {
+        let arch = triple.split('-').next().unwrap();
+        match arch {
+            "x86_64" => Some(Machine::X86_64),
+            "aarch64" => Some(Machine::Aarch64),
+            "mips" | "mipsel" => Some(Machine::Mips),
+            "mips64" | "mips64el" => Some(Machine::Mips64),
+            "powerpc" => Some(Machine::PowerPc),
+            "powerpc64" | "powerpc64le" => Some(Machine::PowerPc64),
+            _ if is_x86(triple) => Some(Machine::X86),
+            _ if arch.starts_with("arm") => Some(Machine::Arm),
+            _ => None
+        }
+    }
+
+    fn from_elf(machine: u16, is_64bit: bool) -> Option<Machine> {
+        match (machine, is_64bit) {
+            (3, _) => Some(Machine::X86),
+            (62, _) => Some(Machine::X86_64),
+            (40, _) => Some(Machine::Arm),
+            (183, _) => Some(Machine::Aarch64),
+            (8, false) => Some(Machine::Mips),
+            (8, true) => Some(Machine::Mips64),
+            (20, _) => Some(Machine::PowerPc),
+            (21, _) => Some(Machine::PowerPc64),
+            _ => None
+        }
+    }
+
+    fn from_macho(cputype: u32) -> Option<Machine> {
+        match cputype {
+            7 => Some(Machine::X86),
+            0x01000007 => Some(Machine::X86_64),
+            12 => Some(Machine::Arm),
+            0x0100000c => Some(Machine::Aarch64),
+            18 => Some(Machine::PowerPc),
+            0x01000012 => Some(Machine::PowerPc64),
+            _ => None
+        }
+    }
+
+    fn from_coff(machine: u16) -> Option<Machine> {
+        match machine {
+            0x14c => Some(Machine::X86),
+            0x8664 => Some(Machine::X86_64),
+            0x1c0 | 0x1c4 => Some(Machine::Arm),
+            _ => None
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Machine::X86 => "x86",
+            Machine::X86_64 => "x86_64",
+            Machine::Arm => "arm",
+            Machine::Aarch64 => "aarch64",
+            Machine::Mips => "mips",
+            Machine::Mips64 => "mips64",
+            Machine::PowerPc => "powerpc",
+            Machine::PowerPc64 => "powerpc64"
+        }
+    }
+}
+
+fn verify_archive(archive: &Path, target: &str) {
+    let expected = match Machine::from_triple(target) {
+        Some(m) => m,
+        None => return  // Nothing to compare with
+    };
+    let data = File::open(archive).read_to_end().unwrap();
+    match archive_machine(&*data) {
+        Some(found) if found != expected => panic!(
+            "{} is built for {}, but the target {} requires {}; \
+             check CC and AR environment variables",
+            archive.display(), found.name(), target, expected.name()
+        ),
+        _ => {}
+    }
+}
+
+// Finds the machine of the first object file inside an ar archive
+fn archive_machine(data: &[u8]) -> Option<Machine> {
+    if !data.starts_with(b"!<arch>\n") { return None; }
+
+    let mut pos = 8;
+    while pos + 60 <= data.len() {
+        let header = &data[pos..pos + 60];
+        let size = match parse_decimal(&header[48..58]) {
+            Some(size) => size,
+            None => return None
+        };
+        let mut start = pos + 60;
+        let end = start + size;
+        if end > data.len() { return None; }
+
+        let name = &header[..16];
+        // BSD archives store long names right before the member contents
+        if name.starts_with(b"#1/") {
+            start += parse_decimal(&name[3..]).unwrap_or(0);
+        }
+        // Skip symbol tables and GNU long names table
+        if !name.starts_with(b"/") && !name.starts_with(b"__.SYMDEF") && start < end {
+            if let Some(m) = object_machine(&data[start..end]) {
+                return Some(m);
+            }
+        }
+
+        // Members are aligned on even offsets
+        pos = end + (end & 1);
+    }
+    None
+}
+
+fn object_machine(obj: &[u8]) -> Option<Machine> {
+    let u16_at = |off: usize, le: bool| if le {
+        (obj[off] as u16) | ((obj[off + 1] as u16) << 8)
+    } else {
+        ((obj[off] as u16) << 8) | (obj[off + 1] as u16)
+    };
+    let u32_at = |off: usize, le: bool| if le {
+        (u16_at(off, le) as u32) | ((u16_at(off + 2, le) as u32) << 16)
+    } else {
+        ((u16_at(off, le) as u32) << 16) | (u16_at(off + 2, le) as u32)
+    };
+
+    if obj.starts_with(b"BC\xc0\xde") {
+        // LLVM bitcode (the lto feature) does not tell its machine in the header
+        None
+    } else if obj.len() >= 20 && obj.starts_with(b"\x7fELF") {
+        Machine::from_elf(u16_at(18, obj[5] == 1), obj[4] == 2)
+    } else if obj.len() >= 8 && (obj.starts_with(b"\xce\xfa\xed\xfe") ||
+                                 obj.starts_with(b"\xcf\xfa\xed\xfe")) {
+        Machine::from_macho(u32_at(4, true))
+    } else if obj.len() >= 8 && (obj.starts_with(b"\xfe\xed\xfa\xce") ||
+                                 obj.starts_with(b"\xfe\xed\xfa\xcf")) {
+        Machine::from_macho(u32_at(4, false))
+    } else if obj.len() >= 2 {
+        Machine::from_coff(u16_at(0, true))
+    } else {
+        None
+    }
+}
+
+fn parse_decimal(bytes: &[u8]) -> Option<usize> {
+    let mut result = None;
+    for &b in bytes.iter() {
+        match b {
+            b'0'...b'9' => result = Some(result.unwrap_or(0) * 10 + (b - b'0') as usize),
+            b' ' if result.is_some() => break,
+            b' ' => continue,
+            _ => return None
+        }
+    }
+    result
+}
+
+fn is_clang(cc: &str) -> bool {
+    let mut words = cc.words();
+    let mut cmd = Command::new(words.next().unwrap_or("cc"));
+    cmd.args(&*words.collect::<Vec<_>>()).arg("--version");
+    cmd.output().map(|po| String::from_utf8_lossy(&*po.output).contains("clang"))
+        .unwrap_or(false)
+}
+
+// Directory and library name of a dynamic clang sanitizer runtime, e.g. `asan`. Older clang
+// puts runtimes of all targets into one directory with the arch in the name,
+// `libclang_rt.asan-x86_64.so`; newer clang has per-target directories, `libclang_rt.asan.so`
+fn clang_runtime(cc: &str, name: &str, target: &str) -> (String, String) {
+    let query = |&: arg: &str| -> Option<String> {
+        let mut words = cc.words();
+        let mut cmd = Command::new(words.next().unwrap_or("cc"));
+        cmd.args(&*words.collect::<Vec<_>>()).arg(arg);
+        cmd.output().ok()
+            .map(|po| String::from_utf8_lossy(&*po.output).trim().to_string())
+            .and_then(|out| if out.is_empty() { None } else { Some(out) })
+    };
+    if target.contains("-apple-") {
+        let lib = format!("clang_rt.{}_osx_dynamic", name);
+        let file = query(&*format!("-print-file-name=lib{}.dylib", lib)).unwrap();
+        return (Path::new(file).dirname_str().unwrap().to_string(), lib);
+    }
+
+    let arch = match target.split('-').next().unwrap() {
+        "i586" | "i686" => "i386",
+        arch if arch.starts_with("arm") && target.ends_with("hf") => "armhf",
+        arch if arch.starts_with("arm") => "arm",
+        arch => arch
+    };
+    let lib = format!("clang_rt.{}-{}", name, arch);
+    // -print-file-name prints the name back unchanged when the file is not found
+    match query(&*format!("-print-file-name=lib{}.so", lib)) {
+        Some(ref file) if Path::new(&**file).is_absolute() => {
+            (Path::new(&**file).dirname_str().unwrap().to_string(), lib)
+        }
+        _ => match query("-print-runtime-dir") {
+            Some(dir) => (dir, format!("clang_rt.{}", name)),
+            None => panic!("cannot find the {} runtime of {}", name, cc)
+        }
+    }
+}
+
+// Features are environment variables for build scripts, and the build is only redone when
+// they change if cargo is told so
+fn feature(name: &str) -> bool {
+    println!("cargo:rerun-if-env-changed=CARGO_FEATURE_{}", name);
+    os::getenv(&*format!("CARGO_FEATURE_{}", name)).is_some()
+}
+
 fn make() -> &'static str {
     if cfg!(target_os = "freebsd") {"gmake"} else {"make"}
 }
@@ -0,0 +1,305 @@
+//! Complex numbers with MPFR real and imaginary parts.
+//!
+//! Both parts of a number have the same precision, and the results of operations have the
+//! precision of `self`, each part rounded in the given direction. Addition, subtraction,
+//! multiplication, `abs` and `arg` are correctly rounded per part. The other functions are
+//! evaluated with `GUARD` extra bits and then rounded, so their error is within a few ulps
+//! of the modulus of the result; a part much smaller than the other, like the real part of
+//! exp(i pi/2), may have a larger relative error. The error of `pow` also grows with the
+//! magnitude of w log z, losing about log2 |w log z| of the guard bits. Special values
+//! (infinite or NaN parts) follow the real formulas rather than the C99 Annex G rules.
+
+use std::num::Float;
+use libc::c_ulong;
+
+use super::{mpfr_prec_t, mpfr_rnd_t, MPFR_RNDN};
+use float::Mpfr;
+use sum::sum_exact;
+use inline::mpfr_zero_p;
+
+// Extra bits of working precision for the functions which are not correctly rounded
+pub const GUARD: mpfr_prec_t = 32;
+
+fn sin_cos(x: &Mpfr, prec: mpfr_prec_t) -> (Mpfr, Mpfr) {
+    let mut s = Mpfr::new(prec);
+    let mut c = Mpfr::new(prec);
+    unsafe { super::mpfr_sin_cos(s.as_mut_ptr(), c.as_mut_ptr(), x.as_ptr(), MPFR_RNDN) };
+    (s, c)
+}
+
+fn sinh_cosh(x: &Mpfr, prec: mpfr_prec_t) -> (Mpfr, Mpfr) {
+    let mut s = Mpfr::new(prec);
+    let mut c = Mpfr::new(prec);
+    unsafe { super::mpfr_sinh_cosh(s.as_mut_ptr(), c.as_mut_ptr(), x.as_ptr(), MPFR_RNDN) };
+    (s, c)
+}
+
+pub struct Complex {
+    re: Mpfr,
+    im: Mpfr
+}
+
+impl Complex {
+    // re + i im with both parts rounded to `prec` bits
+    pub fn new(re: &Mpfr, im: &Mpfr, prec: mpfr_prec_t, rnd: mpfr_rnd_t) -> Complex {
+        Complex { re: re.apply(super::mpfr_set, prec, rnd),
+                  im: im.apply(super::mpfr_set, prec, rnd) }
+    }
+
+    pub fn from_f64(re: f64, im: f64, prec: mpfr_prec_t) -> Complex {
+        Complex::new(&Mpfr::from_f64(re, 53), &Mpfr::from_f64(im, 53), prec, MPFR_RNDN)
+    }
+
+    #[inline]
+    pub fn re(&self) -> &Mpfr { &self.re }
+
+    #[inline]
+    pub fn im(&self) -> &Mpfr { &self.im }
+
+    #[inline]
+    pub fn prec(&self) -> mpfr_prec_t { self.re.prec() }
+
+    // Rounds a result computed at working precision
+    fn round(&self, re: &Mpfr, im: &Mpfr, rnd: mpfr_rnd_t) -> Complex {
+        Complex::new(re, im, self.prec(), rnd)
+    }
+
+    pub fn add(&self, other: &Complex, rnd: mpfr_rnd_t) -> Complex {
+        let prec = self.prec();
+        Complex { re: self.re.apply2(super::mpfr_add, &other.re, prec, rnd),
+                  im: self.im.apply2(super::mpfr_add, &other.im, prec, rnd) }
+    }
+
+    pub fn sub(&self, other: &Complex, rnd: mpfr_rnd_t) -> Complex {
+        let prec = self.prec();
+        Complex { re: self.re.apply2(super::mpfr_sub, &other.re, prec, rnd),
+                  im: self.im.apply2(super::mpfr_sub, &other.im, prec, rnd) }
+    }
+
+    // The products are exact, so each part is rounded once
+    pub fn mul(&self, other: &Complex, rnd: mpfr_rnd_t) -> Complex {
+        let prec = self.prec();
+        let (a, b, c, d) = (&self.re, &self.im, &other.re, &other.im);
+        Complex { re: a.exact_mul(c).apply2(super::mpfr_sub, &b.exact_mul(d), prec, rnd),
+                  im: a.exact_mul(d).apply2(super::mpfr_add, &b.exact_mul(c), prec, rnd) }
+    }
+
+    // (ac + bd)/(c^2 + d^2) + i (bc - ad)/(c^2 + d^2)
+    pub fn div(&self, other: &Complex, rnd: mpfr_rnd_t) -> Complex {
+        let w = self.prec() + GUARD;
+        let (a, b, c, d) = (&self.re, &self.im, &other.re, &other.im);
+        let den = c.exact_mul(c).apply2(super::mpfr_add, &d.exact_mul(d), w, MPFR_RNDN);
+        let re = a.exact_mul(c).apply2(super::mpfr_add, &b.exact_mul(d), w, MPFR_RNDN);
+        let im = b.exact_mul(c).apply2(super::mpfr_sub, &a.exact_mul(d), w, MPFR_RNDN);
+        self.round(&re.apply2(super::mpfr_div, &den, w, MPFR_RNDN),
+                   &im.apply2(super::mpfr_div, &den, w, MPFR_RNDN), rnd)
+    }
+
+    pub fn conj(&self) -> Complex {
+        let mut im = Mpfr::new(self.prec());
+        unsafe { super::mpfr_neg(im.as_mut_ptr(), self.im.as_ptr(), MPFR_RNDN) };
+        Complex { re: self.re.clone(), im: im }
+    }
+
+    // |z|, correctly rounded
+    pub fn abs(&self, rnd: mpfr_rnd_t) -> Mpfr {
+        self.re.apply2(super::mpfr_hypot, &self.im, self.prec(), rnd)
+    }
+
+    // Argument in [-pi, pi], correctly rounded
+    pub fn arg(&self, rnd: mpfr_rnd_t) -> Mpfr {
+        self.im.apply2(super::mpfr_atan2, &self.re, self.prec(), rnd)
+    }
+
+    // e^a (cos b + i sin b)
+    pub fn exp(&self, rnd: mpfr_rnd_t) -> Complex {
+        let w = self.prec() + GUARD;
+        let e = self.re.apply(super::mpfr_exp, w, MPFR_RNDN);
+        let (s, c) = sin_cos(&self.im, w);
+        self.round(&e.apply2(super::mpfr_mul, &c, w, MPFR_RNDN),
+                   &e.apply2(super::mpfr_mul, &s, w, MPFR_RNDN), rnd)
+    }
+
+    // Principal branch: log |z| + i arg z
+    pub fn log(&self, rnd: mpfr_rnd_t) -> Complex {
+        let w = self.prec() + GUARD;
+        let (a, b) = (&self.re, &self.im);
+        // log |z| = log1p(a^2 + b^2 - 1) / 2 with the squares exact and the sum rounded
+        // once, which does not cancel when |z| is close to 1
+        let terms = [a.exact_mul(a), b.exact_mul(b), Mpfr::from_si(-1, 2)];
+        let (t, _) = sum_exact(&terms, w, MPFR_RNDN);
+        let re = if t.to_f64(MPFR_RNDN).abs() <= 0.5 {
+            let mut l = t.apply(super::mpfr_log1p, w, MPFR_RNDN);
+            unsafe { super::mpfr_div_2ui(l.as_mut_ptr(), l.as_ptr(), 1 as c_ulong, MPFR_RNDN) };
+            l.apply(super::mpfr_set, self.prec(), rnd)
+        } else {
+            let abs = a.apply2(super::mpfr_hypot, b, w, MPFR_RNDN);
+            abs.apply(super::mpfr_log, self.prec(), rnd)
+        };
+        Complex { re: re, im: self.arg(rnd) }
+    }
+
+    // Principal branch, with a non-negative real part
+    pub fn sqrt(&self, rnd: mpfr_rnd_t) -> Complex {
+        let w = self.prec() + GUARD;
+        let (a, b) = (&self.re, &self.im);
+        if unsafe { mpfr_zero_p(a.as_ptr()) != 0 && mpfr_zero_p(b.as_ptr()) != 0 } {
+            return self.round(&Mpfr::zero(53), b, rnd);
+        }
+        // t = sqrt((|z| + |a|) / 2), then sqrt(z) = t + i b/(2t) for a >= 0
+        // and |b|/(2t) + i sign(b) t otherwise
+        let abs = a.apply2(super::mpfr_hypot, b, w, MPFR_RNDN);
+        let a_abs = a.apply(super::mpfr_abs, w, MPFR_RNDN);
+        let mut t = abs.apply2(super::mpfr_add, &a_abs, w, MPFR_RNDN);
+        let mut u = Mpfr::new(w);
+        unsafe {
+            super::mpfr_div_2ui(t.as_mut_ptr(), t.as_ptr(), 1 as c_ulong, MPFR_RNDN);
+            super::mpfr_sqrt(t.as_mut_ptr(), t.as_ptr(), MPFR_RNDN);
+            super::mpfr_div(u.as_mut_ptr(), b.as_ptr(), t.as_ptr(), MPFR_RNDN);
+            super::mpfr_div_2ui(u.as_mut_ptr(), u.as_ptr(), 1 as c_ulong, MPFR_RNDN);
+        }
+        if a.sgn() >= 0 {
+            self.round(&t, &u, rnd)
+        } else {
+            unsafe {
+                super::mpfr_abs(u.as_mut_ptr(), u.as_ptr(), MPFR_RNDN);
+                super::mpfr_copysign(t.as_mut_ptr(), t.as_ptr(), b.as_ptr(), MPFR_RNDN);
+            }
+            self.round(&u, &t, rnd)
+        }
+    }
+
+    // Principal value exp(w log z); 0^w is 0 for w with a positive real part
+    pub fn pow(&self, other: &Complex, rnd: mpfr_rnd_t) -> Complex {
+        let prec = self.prec();
+        let w = prec + GUARD;
+        let zero = unsafe {
+            mpfr_zero_p(self.re.as_ptr()) != 0 && mpfr_zero_p(self.im.as_ptr()) != 0 &&
+                other.re.sgn() > 0
+        };
+        if zero {
+            let z = Mpfr::zero(53);
+            return self.round(&z, &z, rnd);
+        }
+        let z = Complex::new(&self.re, &self.im, w, MPFR_RNDN);
+        let e = Complex::new(&other.re, &other.im, w, MPFR_RNDN);
+        let r = e.mul(&z.log(MPFR_RNDN), MPFR_RNDN).exp(MPFR_RNDN);
+        self.round(&r.re, &r.im, rnd)
+    }
+
+    // sin a cosh b + i cos a sinh b
+    pub fn sin(&self, rnd: mpfr_rnd_t) -> Complex {
+        let w = self.prec() + GUARD;
+        let (s, c) = sin_cos(&self.re, w);
+        let (sh, ch) = sinh_cosh(&self.im, w);
+        self.round(&s.apply2(super::mpfr_mul, &ch, w, MPFR_RNDN),
+                   &c.apply2(super::mpfr_mul, &sh, w, MPFR_RNDN), rnd)
+    }
+
+    // cos a cosh b - i sin a sinh b
+    pub fn cos(&self, rnd: mpfr_rnd_t) -> Complex {
+        let w = self.prec() + GUARD;
+        let (s, c) = sin_cos(&self.re, w);
+        let (sh, ch) = sinh_cosh(&self.im, w);
+        let mut im = s.apply2(super::mpfr_mul, &sh, w, MPFR_RNDN);
+        unsafe { super::mpfr_neg(im.as_mut_ptr(), im.as_ptr(), MPFR_RNDN) };
+        self.round(&c.apply2(super::mpfr_mul, &ch, w, MPFR_RNDN), &im, rnd)
+    }
+
+    // sin z / cos z
+    pub fn tan(&self, rnd: mpfr_rnd_t) -> Complex {
+        let w = self.prec() + GUARD;
+        let z = Complex::new(&self.re, &self.im, w, MPFR_RNDN);
+        let r = z.sin(MPFR_RNDN).div(&z.cos(MPFR_RNDN), MPFR_RNDN);
+        self.round(&r.re, &r.im, rnd)
+    }
+}
+
+impl Clone for Complex {
+    fn clone(&self) -> Complex {
+        Complex { re: self.re.clone(), im: self.im.clone() }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::num::Float;
+
+    use super::Complex;
+    use super::super::MPFR_RNDN;
+    use float::Mpfr;
+
+    // Both parts within `tol` relative to the expected modulus
+    fn close(z: &Complex, re: f64, im: f64, tol: f64) -> bool {
+        let scale = (re * re + im * im).sqrt();
+        (z.re().to_f64(MPFR_RNDN) - re).abs() <= tol * scale &&
+            (z.im().to_f64(MPFR_RNDN) - im).abs() <= tol * scale
+    }
+
+    fn exactly(z: &Complex, re: f64, im: f64) -> bool {
+        z.re().to_f64(MPFR_RNDN) == re && z.im().to_f64(MPFR_RNDN) == im
+    }
+
+    #[test]
+    fn arithmetic() {
+        let a = Complex::from_f64(1.0, 2.0, 53);
+        let b = Complex::from_f64(3.0, 4.0, 53);
+        assert!(exactly(&a.add(&b, MPFR_RNDN), 4.0, 6.0));
+        assert!(exactly(&a.sub(&b, MPFR_RNDN), -2.0, -2.0));
+        assert!(exactly(&a.mul(&b, MPFR_RNDN), -5.0, 10.0));
+        assert!(exactly(&Complex::from_f64(-5.0, 10.0, 53).div(&b, MPFR_RNDN), 1.0, 2.0));
+        assert!(exactly(&a.conj(), 1.0, -2.0));
+        assert_eq!(b.abs(MPFR_RNDN).to_f64(MPFR_RNDN), 5.0);
+        assert_eq!(Complex::from_f64(-1.0, 0.0, 53).arg(MPFR_RNDN).to_f64(MPFR_RNDN),
+                   ::std::f64::consts::PI);
+    }
+
+    #[test]
+    fn elementary_functions() {
+        let pi = ::std::f64::consts::PI;
+        // sin of pi rounded to double
+        let sin_pi = 1.2246467991473532e-16;
+        assert!(close(&Complex::from_f64(0.0, pi, 53).exp(MPFR_RNDN), -1.0, sin_pi, 1e-15));
+        assert!(exactly(&Complex::from_f64(-1.0, 0.0, 53).log(MPFR_RNDN), 0.0, pi));
+        assert!(close(&Complex::from_f64(0.0, 1.0, 53).log(MPFR_RNDN), 0.0, pi / 2.0, 1e-15));
+        assert!(exactly(&Complex::from_f64(-4.0, 0.0, 53).sqrt(MPFR_RNDN), 0.0, 2.0));
+        assert!(exactly(&Complex::from_f64(3.0, 4.0, 53).sqrt(MPFR_RNDN), 2.0, 1.0));
+        let two = Complex::from_f64(2.0, 0.0, 53);
+        assert!(close(&Complex::from_f64(1.0, 1.0, 53).pow(&two, MPFR_RNDN), 0.0, 2.0, 1e-15));
+        // sin(1 + i) = sin 1 cosh 1 + i cos 1 sinh 1
+        assert!(close(&Complex::from_f64(1.0, 1.0, 53).sin(MPFR_RNDN),
+                      1.2984575814159773, 0.6349639147847361, 1e-15));
+        assert!(close(&Complex::from_f64(1.0, 1.0, 53).cos(MPFR_RNDN),
+                      0.8337300251311491, -0.9888977057628651, 1e-15));
+        assert!(close(&Complex::from_f64(1.0, 1.0, 53).tan(MPFR_RNDN),
+                      0.27175258531951174, 1.0839233273386946, 1e-15));
+    }
+
+    // log |z| cancels completely if computed as log(hypot(re, im)) for |z| close to 1
+    #[test]
+    fn log_near_the_unit_circle() {
+        let y = 1e-30;
+        let l = Complex::from_f64(1.0, y, 53).log(MPFR_RNDN);
+        // log |z| = log1p(y^2) / 2 = y^2 / 2 - y^4 / 4 + ...
+        let re = l.re().to_f64(MPFR_RNDN);
+        assert!((re - y * y / 2.0).abs() <= 1e-15 * y * y / 2.0);
+        assert_eq!(l.im().to_f64(MPFR_RNDN), y);
+
+        // z^2 = 1 - y^2 + 2 y i
+        let two = Complex::from_f64(2.0, 0.0, 53);
+        let p = Complex::from_f64(1.0, y, 53).pow(&two, MPFR_RNDN);
+        assert_eq!(p.re().to_f64(MPFR_RNDN), 1.0);
+        assert!((p.im().to_f64(MPFR_RNDN) - 2.0 * y).abs() <= 1e-15 * 2.0 * y);
+
+        // |z| just below 1, against log(a^2 + b^2) / 2 with the squares and their sum exact
+        let (a, b) = (0.6, 0.8 - 1e-12);
+        let re = Complex::from_f64(a, b, 53).log(MPFR_RNDN).re().to_f64(MPFR_RNDN);
+        let (ma, mb) = (Mpfr::from_f64(a, 53), Mpfr::from_f64(b, 53));
+        let (a2, b2) = (ma.exact_mul(&ma), mb.exact_mul(&mb));
+        let n = a2.apply2(super::super::mpfr_add, &b2, 300, MPFR_RNDN);
+        let mut l = n.apply(super::super::mpfr_log, 300, MPFR_RNDN);
+        unsafe { super::super::mpfr_div_2ui(l.as_mut_ptr(), l.as_ptr(), 1, MPFR_RNDN) };
+        let expected = l.to_f64(MPFR_RNDN);
+        assert!((re - expected).abs() <= 1e-15 * expected.abs());
+    }
+}
@@ -0,0 +1,269 @@
+//! Per-thread MPFR state and the threading model of the crate.
+//!
+//! MPFR keeps the default precision and rounding mode, the exponent range, the exception
+//! flags and the caches of constants (`mpfr_const_pi`, `mpfr_const_log2`, ...) in global
+//! variables, which are thread-local when MPFR is built with TLS support (the default when
+//! the compiler supports it, reported by `mpfr_buildopt_tls_p()`).
+//!
+//! Values themselves (`InlineFloat`, `Arena`, `MpfrStr`) can be sent to other threads.
+//! Everything here reads or changes the state of the calling thread only, so it panics if
+//! MPFR was built without TLS, when this state would be shared by all threads instead.
+//!
+//! Constant caches are allocated per thread and live until `mpfr_free_cache()` is called
+//! on that thread. Any function of this module, creating an `Mpfr`, or
+//! `free_cache_on_thread_exit()` arranges for it to be called when the thread exits, so
+//! worker threads of a pool do not leak them.
+
+use super::{mpfr_prec_t, mpfr_rnd_t, mpfr_exp_t};
+
+// Whether MPFR state is thread-local
+pub fn is_thread_safe() -> bool {
+    unsafe { super::mpfr_buildopt_tls_p() != 0 }
+}
+
+// Panics unless MPFR state is thread-local, for code changing it temporarily; also
+// registers the cleanup of the caches of the calling thread
+pub fn require_thread_safe() {
+    check_thread_safe(is_thread_safe());
+    free_cache_on_thread_exit();
+}
+
+fn check_thread_safe(tls: bool) {
+    if !tls {
+        panic!("MPFR was built without thread-local storage, its global state is shared by \
+                all threads and cannot be changed safely");
+    }
+}
+
+struct CacheCleanup;
+
+impl Drop for CacheCleanup {
+    fn drop(&mut self) {
+        unsafe { super::mpfr_free_cache() }
+    }
+}
+
+thread_local!(static CACHE_CLEANUP: CacheCleanup = CacheCleanup);
+
+// Makes the current thread call mpfr_free_cache() when it exits; calling it again is cheap
+pub fn free_cache_on_thread_exit() {
+    CACHE_CLEANUP.with(|_| ());
+}
+
+// Like free_cache_on_thread_exit() but does nothing if MPFR was built without TLS, when
+// the caches are shared by all threads and freeing them on exit of one would be a race
+pub fn register_cache_cleanup() {
+    if is_thread_safe() {
+        free_cache_on_thread_exit();
+    }
+}
+
+pub fn default_prec() -> mpfr_prec_t {
+    require_thread_safe();
+    unsafe { super::mpfr_get_default_prec() }
+}
+
+pub fn set_default_prec(prec: mpfr_prec_t) {
+    require_thread_safe();
+    assert!(prec >= super::MPFR_PREC_MIN && prec <= super::MPFR_PREC_MAX,
+            "precision {} is out of range", prec);
+    unsafe { super::mpfr_set_default_prec(prec) }
+}
+
+pub fn default_rounding_mode() -> mpfr_rnd_t {
+    require_thread_safe();
+    unsafe { super::mpfr_get_default_rounding_mode() }
+}
+
+pub fn set_default_rounding_mode(rnd: mpfr_rnd_t) {
+    require_thread_safe();
+    unsafe { super::mpfr_set_default_rounding_mode(rnd) }
+}
+
+pub fn emin() -> mpfr_exp_t {
+    require_thread_safe();
+    unsafe { super::mpfr_get_emin() }
+}
+
+pub fn set_emin(emin: mpfr_exp_t) {
+    require_thread_safe();
+    let r = unsafe { super::mpfr_set_emin(emin) };
+    assert!(r == 0, "emin {} is out of range", emin);
+}
+
+pub fn emax() -> mpfr_exp_t {
+    require_thread_safe();
+    unsafe { super::mpfr_get_emax() }
+}
+
+pub fn set_emax(emax: mpfr_exp_t) {
+    require_thread_safe();
+    let r = unsafe { super::mpfr_set_emax(emax) };
+    assert!(r == 0, "emax {} is out of range", emax);
+}
+
+// Snapshot of the settings of a thread, e.g. to start worker threads with the settings
+// of the thread spawning them
+#[derive(Copy, PartialEq)]
+pub struct Context {
+    pub prec: mpfr_prec_t,
+    pub rnd: mpfr_rnd_t,
+    pub emin: mpfr_exp_t,
+    pub emax: mpfr_exp_t
+}
+
+impl Context {
+    pub fn current() -> Context {
+        Context {
+            prec: default_prec(),
+            rnd: default_rounding_mode(),
+            emin: emin(),
+            emax: emax()
+        }
+    }
+
+    // Makes these settings current for the calling thread
+    pub fn install(&self) {
+        assert!(self.emin <= self.emax, "emin {} is greater than emax {}", self.emin, self.emax);
+        set_default_prec(self.prec);
+        set_default_rounding_mode(self.rnd);
+        set_emin(self.emin);
+        set_emax(self.emax);
+    }
+}
+
+// Sets the default precision and rounding mode of the current thread and restores the
+// previous ones when dropped, including during unwinding.
+pub struct DefaultsGuard {
+    prec: mpfr_prec_t,
+    rnd: mpfr_rnd_t
+}
+
+impl DefaultsGuard {
+    pub fn new(prec: mpfr_prec_t, rnd: mpfr_rnd_t) -> DefaultsGuard {
+        let guard = DefaultsGuard { prec: default_prec(), rnd: default_rounding_mode() };
+        set_default_prec(prec);
+        set_default_rounding_mode(rnd);
+        guard
+    }
+}
+
+impl Drop for DefaultsGuard {
+    fn drop(&mut self) {
+        unsafe {
+            super::mpfr_set_default_prec(self.prec);
+            super::mpfr_set_default_rounding_mode(self.rnd);
+        }
+    }
+}
+
+// Sets the exponent range of the current thread and restores the previous one when
+// dropped, including during unwinding.
+pub struct ExponentRangeGuard {
+    emin: mpfr_exp_t,
+    emax: mpfr_exp_t
+}
+
+impl ExponentRangeGuard {
+    pub fn new(min: mpfr_exp_t, max: mpfr_exp_t) -> ExponentRangeGuard {
+        let guard = ExponentRangeGuard { emin: emin(), emax: emax() };
+        set_emin(min);
+        set_emax(max);
+        guard
+    }
+}
+
+impl Drop for ExponentRangeGuard {
+    fn drop(&mut self) {
+        unsafe {
+            super::mpfr_set_emin(self.emin);
+            super::mpfr_set_emax(self.emax);
+        }
+    }
+}
+
+// Calls `f` with the given default precision, keeping the rounding mode
+pub fn with_precision<T, F: FnOnce() -> T>(prec: mpfr_prec_t, f: F) -> T {
+    let _guard = DefaultsGuard::new(prec, default_rounding_mode());
+    f()
+}
+
+// Calls `f` with the given default precision and rounding mode
+pub fn with_defaults<T, F: FnOnce() -> T>(prec: mpfr_prec_t, rnd: mpfr_rnd_t, f: F) -> T {
+    let _guard = DefaultsGuard::new(prec, rnd);
+    f()
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::mpsc::{channel, Sender};
+    use std::thread::Thread;
+
+    use super::{default_prec, set_default_prec, default_rounding_mode, check_thread_safe};
+    use super::{with_precision, with_defaults, DefaultsGuard, ExponentRangeGuard, emin, emax};
+    use super::super::{mpfr_prec_t, MPFR_RNDN, MPFR_RNDZ, MPFR_RNDU};
+
+    // Reports the default precision when dropped, i.e. after the guards created later
+    struct Observer(Sender<mpfr_prec_t>);
+
+    impl Drop for Observer {
+        fn drop(&mut self) {
+            let _ = self.0.send(default_prec());
+        }
+    }
+
+    #[test]
+    fn guards_restore_defaults() {
+        set_default_prec(64);
+        assert_eq!(with_precision(200, || default_prec()), 200);
+        assert_eq!(default_prec(), 64);
+        assert_eq!(with_defaults(100, MPFR_RNDZ, || (default_prec(), default_rounding_mode())),
+                   (100, MPFR_RNDZ));
+        assert_eq!((default_prec(), default_rounding_mode()), (64, MPFR_RNDN));
+        {
+            let _guard = DefaultsGuard::new(300, MPFR_RNDU);
+            let _inner = DefaultsGuard::new(400, MPFR_RNDZ);
+            assert_eq!(default_prec(), 400);
+        }
+        assert_eq!((default_prec(), default_rounding_mode()), (64, MPFR_RNDN));
+
+        let (min, max) = (emin(), emax());
+        {
+            let _range = ExponentRangeGuard::new(-10, 10);
+            assert_eq!((emin(), emax()), (-10, 10));
+        }
+        assert_eq!((emin(), emax()), (min, max));
+    }
+
+    #[test]
+    fn guards_restore_defaults_on_panic() {
+        let (tx, rx) = channel();
+        let result = Thread::scoped(move || {
+            set_default_prec(64);
+            let _observer = Observer(tx);
+            with_precision(200, || -> () { panic!("unwinding through the guard") });
+        }).join();
+        assert!(result.is_err());
+        assert_eq!(rx.recv().unwrap(), 64);
+
+        let (tx, rx) = channel();
+        let result = Thread::scoped(move || {
+            set_default_prec(64);
+            let _observer = Observer(tx);
+            with_defaults(200, MPFR_RNDZ, || -> () { panic!("unwinding through the guard") });
+        }).join();
+        assert!(result.is_err());
+        assert_eq!(rx.recv().unwrap(), 64);
+    }
+
+    #[test]
+    #[should_panic]
+    fn shared_state_is_rejected() {
+        check_thread_safe(false);
+    }
+
+    #[test]
+    fn thread_local_state_is_accepted() {
+        check_thread_safe(true);
+    }
+}
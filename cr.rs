@@ -0,0 +1,318 @@
+//! Correctly rounded f64 and f32 math functions.
+//!
+//! Each function evaluates the MPFR function at the precision of the target format with the
+//! exponent range of the format, so that the result is rounded once in the caller's
+//! direction, with overflow, underflow and subnormals handled as in IEEE 754. The MPFR
+//! numbers involved are thread-local temporaries, so a call does not allocate.
+//!
+//! The exponent range is changed for the duration of a call, so like the functions of
+//! `context` these panic if MPFR was built without thread-local storage, when the range is
+//! shared by all threads.
+
+use std::cell::RefCell;
+use std::num::Float;
+use libc::{c_int, c_double};
+
+use super::{mpfr_ptr, mpfr_srcptr, mpfr_prec_t, mpfr_rnd_t, mpfr_exp_t, MPFR_RNDN};
+use float::{Mpfr, Fn1, Fn2};
+use context;
+
+#[derive(Copy, PartialEq)]
+pub enum Format {
+    Binary32,
+    Binary64
+}
+
+impl Format {
+    pub fn prec(self) -> mpfr_prec_t {
+        match self { Format::Binary32 => 24, Format::Binary64 => 53 }
+    }
+
+    // Exponent range of the format in MPFR terms, subnormals included
+    pub fn emin(self) -> mpfr_exp_t {
+        match self { Format::Binary32 => -148, Format::Binary64 => -1073 }
+    }
+
+    pub fn emax(self) -> mpfr_exp_t {
+        match self { Format::Binary32 => 128, Format::Binary64 => 1024 }
+    }
+
+    // Whether x is zero, infinite, NaN or has an exponent within the range of the format,
+    // which MPFR requires of the arguments of a function; true of all f32 values for
+    // Binary32 and of all f64 values for Binary64
+    pub fn in_range(self, x: f64) -> bool {
+        let a = x.abs();
+        x == 0.0 || !x.is_finite() ||
+            (a >= 2.0f64.powi(self.emin() as i32 - 1) && a < 2.0f64.powi(self.emax() as i32))
+    }
+
+    // f(x) correctly rounded to this format
+    pub fn correctly_rounded(self, f: Fn1, x: f64, rnd: mpfr_rnd_t) -> f64 {
+        round_with(self, x, 0.0, rnd, |r, x, _| unsafe { f(r, x, rnd) })
+    }
+
+    // f(x, y) correctly rounded to this format
+    pub fn correctly_rounded2(self, f: Fn2, x: f64, y: f64, rnd: mpfr_rnd_t) -> f64 {
+        round_with(self, x, y, rnd, |r, x, y| unsafe { f(r, x, y, rnd) })
+    }
+
+    // x rounded once to this format, which rounding x to the precision of the format and
+    // then to a subnormal would not do
+    pub fn round(self, x: &Mpfr, rnd: mpfr_rnd_t) -> f64 {
+        TEMPS.with(|temps| {
+            let mut temps = temps.borrow_mut();
+            let t = &mut *temps;
+            let r = match self { Format::Binary32 => &mut t.r32, Format::Binary64 => &mut t.r64 };
+            unsafe {
+                // Rounded in the current range, where x is; the ternary value lets
+                // mpfr_subnormalize avoid rounding twice
+                let i = super::mpfr_set(r.as_mut_ptr(), x.as_ptr(), rnd);
+                {
+                    let _range = context::ExponentRangeGuard::new(self.emin(), self.emax());
+                    let i = super::mpfr_check_range(r.as_mut_ptr(), i, rnd);
+                    super::mpfr_subnormalize(r.as_mut_ptr(), i, rnd);
+                }
+                super::mpfr_get_d(r.as_ptr(), rnd) as f64
+            }
+        })
+    }
+}
+
+// Arguments, and results for each format
+struct Temps {
+    x: Mpfr,
+    y: Mpfr,
+    r32: Mpfr,
+    r64: Mpfr
+}
+
+thread_local!(static TEMPS: RefCell<Temps> = RefCell::new(Temps {
+    x: Mpfr::new(53),
+    y: Mpfr::new(53),
+    r32: Mpfr::new(24),
+    r64: Mpfr::new(53)
+}));
+
+// Runs g(r, x, y) with r of the precision of `format` and its exponent range in effect,
+// then rounds the result to the format; g returns the ternary value. Panics if x or y is
+// outside the range of the format.
+fn round_with<G>(format: Format, x: f64, y: f64, rnd: mpfr_rnd_t, g: G) -> f64
+    where G: FnOnce(mpfr_ptr, mpfr_srcptr, mpfr_srcptr) -> c_int
+{
+    assert!(format.in_range(x) && format.in_range(y),
+            "arguments {}, {} are out of the range of the format", x, y);
+    TEMPS.with(|temps| {
+        let mut temps = temps.borrow_mut();
+        let t = &mut *temps;
+        let r = match format { Format::Binary32 => &mut t.r32, Format::Binary64 => &mut t.r64 };
+        unsafe {
+            {
+                let _range = context::ExponentRangeGuard::new(format.emin(), format.emax());
+                // Exact: the arguments have 53 bits and are within the range of the format
+                super::mpfr_set_d(t.x.as_mut_ptr(), x as c_double, MPFR_RNDN);
+                super::mpfr_set_d(t.y.as_mut_ptr(), y as c_double, MPFR_RNDN);
+                let i = g(r.as_mut_ptr(), t.x.as_ptr(), t.y.as_ptr());
+                let i = super::mpfr_check_range(r.as_mut_ptr(), i, rnd);
+                super::mpfr_subnormalize(r.as_mut_ptr(), i, rnd);
+            }
+            // Exact, the result being representable in the format
+            super::mpfr_get_d(r.as_ptr(), rnd) as f64
+        }
+    })
+}
+
+macro_rules! unary {
+    ($($name:ident, $name_f:ident => $f:ident),+) => { $(
+        pub fn $name(x: f64, rnd: mpfr_rnd_t) -> f64 {
+            Format::Binary64.correctly_rounded(super::$f, x, rnd)
+        }
+
+        pub fn $name_f(x: f32, rnd: mpfr_rnd_t) -> f32 {
+            Format::Binary32.correctly_rounded(super::$f, x as f64, rnd) as f32
+        }
+    )+ }
+}
+
+macro_rules! binary {
+    ($($name:ident, $name_f:ident => $f:ident),+) => { $(
+        pub fn $name(x: f64, y: f64, rnd: mpfr_rnd_t) -> f64 {
+            Format::Binary64.correctly_rounded2(super::$f, x, y, rnd)
+        }
+
+        pub fn $name_f(x: f32, y: f32, rnd: mpfr_rnd_t) -> f32 {
+            Format::Binary32.correctly_rounded2(super::$f, x as f64, y as f64, rnd) as f32
+        }
+    )+ }
+}
+
+unary!(
+    cr_exp, cr_expf => mpfr_exp,
+    cr_exp2, cr_exp2f => mpfr_exp2,
+    cr_exp10, cr_exp10f => mpfr_exp10,
+    cr_expm1, cr_expm1f => mpfr_expm1,
+    cr_log, cr_logf => mpfr_log,
+    cr_log2, cr_log2f => mpfr_log2,
+    cr_log10, cr_log10f => mpfr_log10,
+    cr_log1p, cr_log1pf => mpfr_log1p,
+    cr_sin, cr_sinf => mpfr_sin,
+    cr_cos, cr_cosf => mpfr_cos,
+    cr_tan, cr_tanf => mpfr_tan,
+    cr_asin, cr_asinf => mpfr_asin,
+    cr_acos, cr_acosf => mpfr_acos,
+    cr_atan, cr_atanf => mpfr_atan,
+    cr_sinh, cr_sinhf => mpfr_sinh,
+    cr_cosh, cr_coshf => mpfr_cosh,
+    cr_tanh, cr_tanhf => mpfr_tanh,
+    cr_asinh, cr_asinhf => mpfr_asinh,
+    cr_acosh, cr_acoshf => mpfr_acosh,
+    cr_atanh, cr_atanhf => mpfr_atanh,
+    cr_sqrt, cr_sqrtf => mpfr_sqrt,
+    cr_cbrt, cr_cbrtf => mpfr_cbrt,
+    cr_erf, cr_erff => mpfr_erf,
+    cr_erfc, cr_erfcf => mpfr_erfc,
+    cr_tgamma, cr_tgammaf => mpfr_gamma,
+    cr_j0, cr_j0f => mpfr_j0,
+    cr_j1, cr_j1f => mpfr_j1,
+    cr_y0, cr_y0f => mpfr_y0,
+    cr_y1, cr_y1f => mpfr_y1
+);
+
+binary!(
+    cr_pow, cr_powf => mpfr_pow,
+    cr_atan2, cr_atan2f => mpfr_atan2,
+    cr_hypot, cr_hypotf => mpfr_hypot
+);
+
+// log|Gamma(x)|, +Inf at the poles as in C; the sign of Gamma(x) is that of cr_tgamma(x)
+pub fn cr_lgamma(x: f64, rnd: mpfr_rnd_t) -> f64 {
+    let mut sign: c_int = 0;
+    round_with(Format::Binary64, x, 0.0, rnd,
+               |r, x, _| unsafe { super::mpfr_lgamma(r, &mut sign, x, rnd) })
+}
+
+pub fn cr_lgammaf(x: f32, rnd: mpfr_rnd_t) -> f32 {
+    let mut sign: c_int = 0;
+    round_with(Format::Binary32, x as f64, 0.0, rnd,
+               |r, x, _| unsafe { super::mpfr_lgamma(r, &mut sign, x, rnd) }) as f32
+}
+
+#[cfg(test)]
+mod test {
+    use std::f64;
+    use std::f32;
+    use std::mem;
+    use std::num::Float;
+    use libc::c_long;
+
+    use super::{cr_exp, cr_expf, cr_log, cr_sqrtf, cr_pow, Format};
+    use super::super::{mpfr_exp, MPFR_RNDN, MPFR_RNDZ, MPFR_RNDU, MPFR_RNDD};
+    use float::Mpfr;
+    use context;
+
+    // Largest finite values
+    const MAX64: f64 = 1.7976931348623157e308;
+    const MAX32: f32 = 3.4028234663852886e38;
+
+    // k times the smallest subnormal f32
+    fn subnormal32(k: u32) -> f32 {
+        unsafe { mem::transmute(k) }
+    }
+
+    #[test]
+    fn overflow() {
+        assert_eq!(cr_exp(1000.0, MPFR_RNDN), f64::INFINITY);
+        assert_eq!(cr_exp(1000.0, MPFR_RNDU), f64::INFINITY);
+        assert_eq!(cr_exp(1000.0, MPFR_RNDZ), MAX64);
+        assert_eq!(cr_exp(1000.0, MPFR_RNDD), MAX64);
+        // exp(89) is about 4.5e38, beyond f32 but not f64
+        assert_eq!(cr_expf(89.0, MPFR_RNDN), f32::INFINITY);
+        assert_eq!(cr_expf(89.0, MPFR_RNDZ), MAX32);
+        assert_eq!(cr_exp(-1000.0, MPFR_RNDD), 0.0);
+    }
+
+    #[test]
+    fn underflow_and_subnormals() {
+        // exp(-745) is 0.57 and exp(-746) 0.21 times the smallest subnormal f64
+        assert_eq!(cr_exp(-745.0, MPFR_RNDN), 5e-324);
+        assert_eq!(cr_exp(-745.0, MPFR_RNDD), 0.0);
+        assert_eq!(cr_exp(-746.0, MPFR_RNDN), 0.0);
+        assert_eq!(cr_exp(-746.0, MPFR_RNDU), 5e-324);
+        // exp(-100) is 26.55 times the smallest subnormal f32
+        assert_eq!(cr_expf(-100.0, MPFR_RNDN), subnormal32(27));
+        assert_eq!(cr_expf(-100.0, MPFR_RNDD), subnormal32(26));
+        assert_eq!(cr_expf(-100.0, MPFR_RNDU), subnormal32(27));
+    }
+
+    #[test]
+    fn directed_rounding() {
+        assert_eq!(cr_log(2.0, MPFR_RNDN), 0.6931471805599453);
+        assert_eq!(cr_log(2.0, MPFR_RNDD), 0.6931471805599453);
+        assert_eq!(cr_log(2.0, MPFR_RNDU), 0.6931471805599454);
+        assert_eq!(cr_sqrtf(2.0, MPFR_RNDN), 1.4142135381698608);
+        assert_eq!(cr_sqrtf(2.0, MPFR_RNDZ), 1.4142135381698608);
+        assert_eq!(cr_sqrtf(2.0, MPFR_RNDU), 1.4142136573791504);
+        assert_eq!(cr_pow(10.0, -0.5, MPFR_RNDN), 0.31622776601683794);
+        assert_eq!(cr_pow(10.0, -0.5, MPFR_RNDZ), 0.3162277660168379);
+        // Exact results are not affected by the direction
+        assert_eq!(cr_pow(2.0, 10.0, MPFR_RNDD), 1024.0);
+    }
+
+    #[test]
+    fn exponent_range_is_restored() {
+        let (emin, emax) = (context::emin(), context::emax());
+        cr_exp(1000.0, MPFR_RNDN);
+        cr_expf(-100.0, MPFR_RNDN);
+        assert_eq!((context::emin(), context::emax()), (emin, emax));
+    }
+
+    #[test]
+    fn arguments_do_not_depend_on_the_exponent_range() {
+        let emax = context::emax();
+        context::set_emax(10);
+        let r = cr_log(2000.0, MPFR_RNDN);
+        context::set_emax(emax);
+        assert_eq!(r, 7.600902459542082);
+    }
+
+    #[test]
+    #[should_panic]
+    fn binary32_arguments_out_of_range() {
+        Format::Binary32.correctly_rounded(mpfr_exp, 1e300, MPFR_RNDN);
+    }
+
+    #[test]
+    fn range_checks() {
+        assert!(Format::Binary32.in_range(MAX32 as f64));
+        assert!(Format::Binary32.in_range(2.0f64.powi(-149)));
+        assert!(!Format::Binary32.in_range(1e-46));
+        assert!(!Format::Binary32.in_range(1e39));
+        assert!(Format::Binary64.in_range(5e-324) && Format::Binary64.in_range(1e308));
+    }
+
+    // m * 2^e at 100 bits
+    fn scaled(m: f64, e: c_long) -> Mpfr {
+        let mut x = Mpfr::from_f64(m, 100);
+        unsafe { super::super::mpfr_mul_2si(x.as_mut_ptr(), x.as_ptr(), e, MPFR_RNDN) };
+        x
+    }
+
+    #[test]
+    fn rounding_mpfr_values() {
+        let (emin, emax) = (context::emin(), context::emax());
+        let tiny = 2.0f64.powi(-1074);
+        // Just above half the smallest subnormal; rounding to 53 bits first would give
+        // exactly half of it, and then 0
+        let x = scaled(1.0 + 2.0f64.powi(-65), -1075);
+        assert_eq!(Format::Binary64.round(&x, MPFR_RNDN), tiny);
+        assert_eq!(Format::Binary64.round(&x, MPFR_RNDZ), 0.0);
+        assert_eq!(Format::Binary64.round(&scaled(1.0, -1075), MPFR_RNDN), 0.0);
+        assert_eq!(Format::Binary64.round(&scaled(3.0, -1076), MPFR_RNDN), tiny);
+        assert_eq!(Format::Binary64.round(&scaled(1.0, 1024), MPFR_RNDN), f64::INFINITY);
+        assert_eq!(Format::Binary64.round(&scaled(1.0, 1024), MPFR_RNDZ), MAX64);
+        assert_eq!(Format::Binary64.round(&scaled(1.0 + 2.0f64.powi(-60), 0), MPFR_RNDU),
+                   1.0 + 2.0f64.powi(-52));
+        assert_eq!(Format::Binary32.round(&scaled(1.0, -150), MPFR_RNDU),
+                   subnormal32(1) as f64);
+        assert_eq!((context::emin(), context::emax()), (emin, emax));
+    }
+}
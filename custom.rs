@@ -0,0 +1,103 @@
+//! Floating-point numbers keeping their significand inline, without heap allocation.
+//!
+//! Built on the custom interface of MPFR (`mpfr_custom_*`). Significand limbs are stored in
+//! a fixed-size array which is a part of the value itself, e.g. `InlineFloat<[mp_limb_t; 4]>`
+//! holds up to 256 bits on 64-bit platforms. Since Rust moves values by copying them, the
+//! pointer to the limbs inside `__mpfr_struct` is updated every time the raw pointer to the
+//! value is requested.
+
+use std::cell::UnsafeCell;
+use std::mem;
+use libc::c_void;
+use gmp::mp_limb_t;
+
+use super::{__mpfr_struct, mpfr_ptr, mpfr_srcptr, mpfr_prec_t, MPFR_NAN_KIND, MPFR_PREC_MIN};
+use inline::{mpfr_custom_get_size, mpfr_custom_init_set, mpfr_custom_move};
+
+// Fixed-size storage for significand limbs; implemented for arrays of mp_limb_t.
+// Unsafe because MPFR reads up to `limbs_len()` limbs through `limbs_ptr()` and writes
+// them through `limbs_mut_ptr()`.
+pub unsafe trait Limbs: Copy {
+    fn limbs_len(&self) -> usize;
+    fn limbs_ptr(&self) -> *const mp_limb_t;
+    fn limbs_mut_ptr(&mut self) -> *mut mp_limb_t;
+}
+
+macro_rules! limbs_impl {
+    ($($n:expr),+) => { $(
+        unsafe impl Limbs for [mp_limb_t; $n] {
+            #[inline]
+            fn limbs_len(&self) -> usize { $n }
+
+            #[inline]
+            fn limbs_ptr(&self) -> *const mp_limb_t { self.as_ptr() }
+
+            #[inline]
+            fn limbs_mut_ptr(&mut self) -> *mut mp_limb_t { self.as_mut_ptr() }
+        }
+    )+ }
+}
+
+limbs_impl!(1, 2, 3, 4, 5, 6, 7, 8, 12, 16, 24, 32, 48, 64);
+
+pub struct InlineFloat<S> {
+    raw: UnsafeCell<__mpfr_struct>,
+    limbs: S
+}
+
+impl<S: Limbs> InlineFloat<S> {
+    // Creates NaN with the given precision, like MPFR_DECL_INIT does.
+    // Panics if the precision does not fit into the storage.
+    pub fn new(prec: mpfr_prec_t) -> InlineFloat<S> {
+        let limbs: S = unsafe { mem::zeroed() };
+        let size = unsafe { mpfr_custom_get_size(prec) } as usize;
+        assert!(prec >= MPFR_PREC_MIN, "precision {} is less than MPFR_PREC_MIN", prec);
+        assert!(size <= limbs.limbs_len() * mem::size_of::<mp_limb_t>(),
+                "precision {} needs {} bytes of storage, only {} available",
+                prec, size, limbs.limbs_len() * mem::size_of::<mp_limb_t>());
+
+        let mut raw: __mpfr_struct = unsafe { mem::zeroed() };
+        unsafe {
+            // The pointer is set for real on access, when the value is at its final place
+            mpfr_custom_init_set(&mut raw, MPFR_NAN_KIND as ::libc::c_int, 0, prec,
+                                 0 as *mut c_void);
+        }
+        InlineFloat { raw: UnsafeCell::new(raw), limbs: limbs }
+    }
+
+    #[inline]
+    pub fn prec(&self) -> mpfr_prec_t {
+        unsafe { (*self.raw.get())._mpfr_prec }
+    }
+
+    // The pointer is valid until the value is moved; MPFR only reads through it
+    #[inline]
+    pub fn as_ptr(&self) -> mpfr_srcptr {
+        unsafe {
+            mpfr_custom_move(self.raw.get(), self.limbs.limbs_ptr() as *mut c_void);
+            self.raw.get() as mpfr_srcptr
+        }
+    }
+
+    // The pointer is valid until the value is moved
+    #[inline]
+    pub fn as_mut_ptr(&mut self) -> mpfr_ptr {
+        unsafe {
+            mpfr_custom_move(self.raw.get(), self.limbs.limbs_mut_ptr() as *mut c_void);
+            self.raw.get()
+        }
+    }
+}
+
+// The significand pointer is re-pointed to the limbs on every access, so it never refers
+// to memory of another thread
+unsafe impl<S: Limbs + Send> Send for InlineFloat<S> {}
+
+impl<S: Limbs> Clone for InlineFloat<S> {
+    fn clone(&self) -> InlineFloat<S> {
+        InlineFloat {
+            raw: UnsafeCell::new(unsafe { *self.raw.get() }),
+            limbs: self.limbs
+        }
+    }
+}
@@ -0,0 +1,18 @@
+#![allow(unstable)]
+
+extern crate "mpfr-sys" as mpfr;
+
+use std::ffi::c_str_to_bytes;
+use std::str;
+
+// Links libmpfr and libgmp into a single executable and calls into them, e.g.
+//
+//     cargo run --example static_version --target x86_64-unknown-linux-musl
+//
+// For musl targets the result is a fully static binary, which can be checked with `ldd`.
+fn main() {
+    let version = unsafe { c_str_to_bytes(&mpfr::mpfr_get_version()) };
+    let version = str::from_utf8(version).unwrap();
+    assert!(!version.is_empty());
+    println!("libmpfr {}", version);
+}
@@ -0,0 +1,133 @@
+//! An owned MPFR value, initialized with `mpfr_init2` and cleared on drop.
+//!
+//! Safe wrappers in other modules take and return `Mpfr`; the raw pointers give access
+//! to the rest of the MPFR API.
+
+use std::cmp::Ordering;
+use std::mem;
+use libc::{c_int, c_long, c_ulong, c_double};
+
+use super::{__mpfr_struct, mpfr_ptr, mpfr_srcptr, mpfr_prec_t, mpfr_rnd_t,
+            MPFR_PREC_MIN, MPFR_PREC_MAX, MPFR_RNDN};
+use inline::{mpfr_nan_p, mpfr_sgn};
+use context;
+
+// MPFR functions of one and two arguments, e.g. mpfr_sqrt and mpfr_add
+pub type Fn1 = unsafe extern "C" fn(mpfr_ptr, mpfr_srcptr, mpfr_rnd_t) -> c_int;
+pub type Fn2 = unsafe extern "C" fn(mpfr_ptr, mpfr_srcptr, mpfr_srcptr, mpfr_rnd_t) -> c_int;
+
+pub struct Mpfr {
+    raw: __mpfr_struct
+}
+
+impl Mpfr {
+    // Creates NaN with the given precision
+    pub fn new(prec: mpfr_prec_t) -> Mpfr {
+        assert!(prec >= MPFR_PREC_MIN && prec <= MPFR_PREC_MAX,
+                "precision {} is out of range", prec);
+        // Computations with the value may fill the constant caches of this thread
+        context::register_cache_cleanup();
+        let mut raw: __mpfr_struct = unsafe { mem::zeroed() };
+        unsafe { super::mpfr_init2(&mut raw, prec) };
+        Mpfr { raw: raw }
+    }
+
+    // The conversion is exact for precisions of 53 bits and more
+    pub fn from_f64(x: f64, prec: mpfr_prec_t) -> Mpfr {
+        let mut r = Mpfr::new(prec);
+        unsafe { super::mpfr_set_d(r.as_mut_ptr(), x as c_double, MPFR_RNDN) };
+        r
+    }
+
+    pub fn zero(prec: mpfr_prec_t) -> Mpfr {
+        let mut r = Mpfr::new(prec);
+        unsafe { super::mpfr_set_zero(r.as_mut_ptr(), 1) };
+        r
+    }
+
+    // The conversions are exact if the integer fits in `prec` bits
+    pub fn from_si(x: c_long, prec: mpfr_prec_t) -> Mpfr {
+        let mut r = Mpfr::new(prec);
+        unsafe { super::mpfr_set_si(r.as_mut_ptr(), x, MPFR_RNDN) };
+        r
+    }
+
+    pub fn from_ui(x: c_ulong, prec: mpfr_prec_t) -> Mpfr {
+        let mut r = Mpfr::new(prec);
+        unsafe { super::mpfr_set_ui(r.as_mut_ptr(), x, MPFR_RNDN) };
+        r
+    }
+
+    pub fn to_f64(&self, rnd: mpfr_rnd_t) -> f64 {
+        unsafe { super::mpfr_get_d(self.as_ptr(), rnd) as f64 }
+    }
+
+    #[inline]
+    pub fn prec(&self) -> mpfr_prec_t { self.raw._mpfr_prec }
+
+    #[inline]
+    pub fn is_nan(&self) -> bool {
+        unsafe { mpfr_nan_p(self.as_ptr()) != 0 }
+    }
+
+    // Like mpfr_sgn: 0 for zeros and NaN
+    #[inline]
+    pub fn sgn(&self) -> c_int {
+        unsafe { mpfr_sgn(self.as_ptr()) }
+    }
+
+    // f(self) rounded to `prec` bits
+    pub fn apply(&self, f: Fn1, prec: mpfr_prec_t, rnd: mpfr_rnd_t) -> Mpfr {
+        let mut r = Mpfr::new(prec);
+        unsafe { f(r.as_mut_ptr(), self.as_ptr(), rnd) };
+        r
+    }
+
+    // f(self, y) rounded to `prec` bits
+    pub fn apply2(&self, f: Fn2, y: &Mpfr, prec: mpfr_prec_t, rnd: mpfr_rnd_t) -> Mpfr {
+        let mut r = Mpfr::new(prec);
+        unsafe { f(r.as_mut_ptr(), self.as_ptr(), y.as_ptr(), rnd) };
+        r
+    }
+
+    // The product with the sum of both precisions, which is exact
+    pub fn exact_mul(&self, y: &Mpfr) -> Mpfr {
+        self.apply2(super::mpfr_mul, y, self.prec() + y.prec(), MPFR_RNDN)
+    }
+
+    #[inline]
+    pub fn as_ptr(&self) -> mpfr_srcptr { &self.raw }
+
+    #[inline]
+    pub fn as_mut_ptr(&mut self) -> mpfr_ptr { &mut self.raw }
+}
+
+impl Clone for Mpfr {
+    fn clone(&self) -> Mpfr {
+        let mut r = Mpfr::new(self.prec());
+        unsafe { super::mpfr_set(r.as_mut_ptr(), self.as_ptr(), MPFR_RNDN) };
+        r
+    }
+}
+
+impl Drop for Mpfr {
+    fn drop(&mut self) {
+        unsafe { super::mpfr_clear(&mut self.raw) }
+    }
+}
+
+// The significand is owned by the value
+unsafe impl Send for Mpfr {}
+
+// Converts a ternary value to the direction of rounding: Greater if the rounded result is
+// greater than the exact one, Less if it is less, Equal if the result is exact
+#[inline]
+pub fn ordering(ternary: c_int) -> Ordering {
+    if ternary > 0 {
+        Ordering::Greater
+    } else if ternary < 0 {
+        Ordering::Less
+    } else {
+        Ordering::Equal
+    }
+}
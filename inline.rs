@@ -0,0 +1,256 @@
+//! Rust versions of functions which mpfr.h implements as macros on `__mpfr_struct` fields.
+//!
+//! Functions here have the same signatures as their out-of-line counterparts in the crate
+//! root but read the structure fields directly, so they can be inlined into the caller.
+
+use std::mem;
+use libc::{c_int, c_uint, c_ulong, c_long, c_void, size_t};
+use gmp::mp_limb_t;
+
+use super::{mpfr_ptr, mpfr_srcptr, mpfr_prec_t, mpfr_exp_t,
+            MPFR_NAN_KIND, MPFR_INF_KIND, MPFR_ZERO_KIND, MPFR_REGULAR_KIND,
+            __MPFR_EXP_NAN, __MPFR_EXP_INF, __MPFR_EXP_ZERO};
+
+#[inline]
+pub unsafe fn mpfr_nan_p(x: mpfr_srcptr) -> c_int {
+    ((*x)._mpfr_exp == __MPFR_EXP_NAN) as c_int
+}
+
+#[inline]
+pub unsafe fn mpfr_inf_p(x: mpfr_srcptr) -> c_int {
+    ((*x)._mpfr_exp == __MPFR_EXP_INF) as c_int
+}
+
+#[inline]
+pub unsafe fn mpfr_zero_p(x: mpfr_srcptr) -> c_int {
+    ((*x)._mpfr_exp == __MPFR_EXP_ZERO) as c_int
+}
+
+#[inline]
+pub unsafe fn mpfr_regular_p(x: mpfr_srcptr) -> c_int {
+    ((*x)._mpfr_exp > __MPFR_EXP_INF) as c_int
+}
+
+// Like the C version, sets the erange flag for NaN
+#[inline]
+pub unsafe fn mpfr_sgn(x: mpfr_srcptr) -> c_int {
+    if (*x)._mpfr_exp < __MPFR_EXP_INF {
+        if mpfr_nan_p(x) != 0 {
+            super::mpfr_set_erangeflag();
+        }
+        0
+    } else {
+        (*x)._mpfr_sign
+    }
+}
+
+#[inline]
+pub unsafe fn mpfr_get_prec(x: mpfr_srcptr) -> mpfr_prec_t {
+    (*x)._mpfr_prec
+}
+
+#[inline]
+pub unsafe fn mpfr_get_exp(x: mpfr_srcptr) -> mpfr_exp_t {
+    (*x)._mpfr_exp
+}
+
+#[inline]
+pub unsafe fn mpfr_signbit(x: mpfr_srcptr) -> c_int {
+    ((*x)._mpfr_sign < 0) as c_int
+}
+
+#[inline]
+pub unsafe fn mpfr_cmp_ui(b: mpfr_srcptr, i: c_ulong) -> c_int {
+    if i == 0 { mpfr_sgn(b) } else { super::mpfr_cmp_ui_2exp(b, i, 0) }
+}
+
+#[inline]
+pub unsafe fn mpfr_cmp_si(b: mpfr_srcptr, i: c_long) -> c_int {
+    if i >= 0 { mpfr_cmp_ui(b, i as c_ulong) } else { super::mpfr_cmp_si_2exp(b, i, 0) }
+}
+
+#[inline]
+pub unsafe fn mpfr_custom_get_size(prec: mpfr_prec_t) -> size_t {
+    let numb_bits = (mem::size_of::<mp_limb_t>() * 8) as mpfr_prec_t;
+    ((prec + numb_bits - 1) / numb_bits) as size_t * mem::size_of::<mp_limb_t>() as size_t
+}
+
+#[inline]
+pub unsafe fn mpfr_custom_init(_significand: *mut c_void, _prec: mpfr_prec_t) {}
+
+#[inline]
+pub unsafe fn mpfr_custom_get_significand(x: mpfr_srcptr) -> *mut c_void {
+    (*x)._mpfr_d as *mut c_void
+}
+
+#[inline]
+pub unsafe fn mpfr_custom_get_exp(x: mpfr_srcptr) -> mpfr_exp_t {
+    (*x)._mpfr_exp
+}
+
+#[inline]
+pub unsafe fn mpfr_custom_move(x: mpfr_ptr, significand: *mut c_void) {
+    (*x)._mpfr_d = significand as *mut mp_limb_t;
+}
+
+#[inline]
+pub unsafe fn mpfr_custom_init_set(x: mpfr_ptr, kind: c_int, exp: mpfr_exp_t,
+                                   prec: mpfr_prec_t, significand: *mut c_void) {
+    let (t, sign) = if kind >= 0 { (kind as c_uint, 1) } else { (-kind as c_uint, -1) };
+    (*x)._mpfr_prec = prec;
+    (*x)._mpfr_sign = sign;
+    (*x)._mpfr_exp = match t {
+        MPFR_REGULAR_KIND => exp,
+        MPFR_NAN_KIND => __MPFR_EXP_NAN,
+        MPFR_INF_KIND => __MPFR_EXP_INF,
+        _ => __MPFR_EXP_ZERO
+    };
+    (*x)._mpfr_d = significand as *mut mp_limb_t;
+}
+
+#[inline]
+pub unsafe fn mpfr_custom_get_kind(x: mpfr_srcptr) -> c_int {
+    let exp = (*x)._mpfr_exp;
+    let sign = (*x)._mpfr_sign;
+    if exp > __MPFR_EXP_INF {
+        MPFR_REGULAR_KIND as c_int * sign
+    } else if exp == __MPFR_EXP_INF {
+        MPFR_INF_KIND as c_int * sign
+    } else if exp == __MPFR_EXP_NAN {
+        MPFR_NAN_KIND as c_int
+    } else {
+        MPFR_ZERO_KIND as c_int * sign
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use libc::{c_int, c_long, c_ulong, c_void};
+
+    use super::super::mpfr_ptr;
+    use float::Mpfr;
+
+    fn sign(x: c_int) -> c_int {
+        if x > 0 { 1 } else if x < 0 { -1 } else { 0 }
+    }
+
+    // NaN, +Inf, -Inf, +0, -0 and regular values of both signs
+    fn values() -> Vec<Mpfr> {
+        let mut xs: Vec<Mpfr> = [0.0, -0.0, 1.0, -1.0, 0.75, -3.5e300, 1e-300].iter()
+            .map(|&x| Mpfr::from_f64(x, 53)).collect();
+        let mut nan = Mpfr::new(64);
+        let mut inf = Mpfr::new(64);
+        let mut neg_inf = Mpfr::new(64);
+        unsafe {
+            super::super::mpfr_set_nan(nan.as_mut_ptr());
+            super::super::mpfr_set_inf(inf.as_mut_ptr(), 1);
+            super::super::mpfr_set_inf(neg_inf.as_mut_ptr(), -1);
+        }
+        xs.push(nan);
+        xs.push(inf);
+        xs.push(neg_inf);
+        xs
+    }
+
+    #[test]
+    fn predicates() {
+        for x in values().iter() {
+            let p = x.as_ptr();
+            unsafe {
+                assert_eq!(super::mpfr_nan_p(p), super::super::mpfr_nan_p(p));
+                assert_eq!(super::mpfr_inf_p(p), super::super::mpfr_inf_p(p));
+                assert_eq!(super::mpfr_zero_p(p), super::super::mpfr_zero_p(p));
+                assert_eq!(super::mpfr_regular_p(p), super::super::mpfr_regular_p(p));
+                assert_eq!(super::mpfr_signbit(p), super::super::mpfr_signbit(p));
+                assert_eq!(super::mpfr_get_prec(p), super::super::mpfr_get_prec(p));
+                assert_eq!(super::mpfr_custom_get_exp(p), super::super::mpfr_custom_get_exp(p));
+                assert_eq!(super::mpfr_custom_get_kind(p), super::super::mpfr_custom_get_kind(p));
+                assert_eq!(super::mpfr_custom_get_significand(p),
+                           super::super::mpfr_custom_get_significand(p));
+                if super::super::mpfr_regular_p(p) != 0 {
+                    assert_eq!(super::mpfr_get_exp(p), super::super::mpfr_get_exp(p));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn sgn_and_comparisons() {
+        for x in values().iter() {
+            let p = x.as_ptr();
+            unsafe {
+                super::super::mpfr_clear_erangeflag();
+                let expected = super::super::mpfr_sgn(p);
+                let expected_erange = super::super::mpfr_erangeflag_p();
+                super::super::mpfr_clear_erangeflag();
+                assert_eq!(sign(super::mpfr_sgn(p)), sign(expected));
+                assert_eq!(super::super::mpfr_erangeflag_p(), expected_erange);
+
+                for &i in [0 as c_ulong, 1, 3].iter() {
+                    assert_eq!(sign(super::mpfr_cmp_ui(p, i)),
+                               sign(super::super::mpfr_cmp_ui(p, i)));
+                }
+                for &i in [0 as c_long, 1, -1, -4].iter() {
+                    assert_eq!(sign(super::mpfr_cmp_si(p, i)),
+                               sign(super::super::mpfr_cmp_si(p, i)));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn sgn_of_nan_sets_erange() {
+        let mut nan = Mpfr::new(53);
+        unsafe {
+            super::super::mpfr_set_nan(nan.as_mut_ptr());
+            super::super::mpfr_clear_erangeflag();
+            assert_eq!(super::mpfr_sgn(nan.as_ptr()), 0);
+            assert!(super::super::mpfr_erangeflag_p() != 0);
+            super::super::mpfr_clear_erangeflag();
+        }
+    }
+
+    #[test]
+    fn custom_interface() {
+        for &prec in [2, 53, 64, 65, 200].iter() {
+            unsafe {
+                assert_eq!(super::mpfr_custom_get_size(prec),
+                           super::super::mpfr_custom_get_size(prec));
+            }
+        }
+
+        // Both versions build the same value from the same limbs, as many as 100 bits take
+        // with the limb size of this GMP; the most significant one is the last
+        let limb_size = ::std::mem::size_of::<::gmp::mp_limb_t>();
+        let size = unsafe { super::super::mpfr_custom_get_size(100) } as usize;
+        let mut limbs = vec![0 as ::gmp::mp_limb_t; size / limb_size];
+        let last = limbs.len() - 1;
+        limbs[last] = 1 << (limb_size * 8 - 1);
+        let significand = limbs.as_mut_ptr() as *mut c_void;
+        let (regular, nan) = (super::MPFR_REGULAR_KIND as c_int, super::MPFR_NAN_KIND as c_int);
+        let (inf, zero) = (super::MPFR_INF_KIND as c_int, super::MPFR_ZERO_KIND as c_int);
+        for &kind in [regular, -regular, nan, inf, -inf, zero, -zero].iter() {
+            let mut a = Mpfr::new(100);
+            let mut b = Mpfr::new(100);
+            unsafe {
+                let (pa, pb): (mpfr_ptr, mpfr_ptr) = (a.as_mut_ptr(), b.as_mut_ptr());
+                let (da, db) = ((*pa)._mpfr_d, (*pb)._mpfr_d);
+                super::mpfr_custom_init_set(pa, kind, 5, 100, significand);
+                super::super::mpfr_custom_init_set(pb, kind, 5, 100, significand);
+                assert_eq!((*pa)._mpfr_prec, (*pb)._mpfr_prec);
+                assert_eq!((*pa)._mpfr_exp, (*pb)._mpfr_exp);
+                assert_eq!((*pa)._mpfr_d, (*pb)._mpfr_d);
+                if super::super::mpfr_nan_p(pa) == 0 {
+                    assert_eq!((*pa)._mpfr_sign, (*pb)._mpfr_sign);
+                }
+                assert!(super::super::mpfr_cmp(pa, pb) == 0 || super::super::mpfr_nan_p(pa) != 0);
+
+                // Moving the significands back lets Drop free what mpfr_init2 allocated
+                super::mpfr_custom_move(pa, da as *mut c_void);
+                super::super::mpfr_custom_move(pb, db as *mut c_void);
+                assert_eq!((*pa)._mpfr_d, da);
+                assert_eq!((*pb)._mpfr_d, db);
+            }
+        }
+    }
+}
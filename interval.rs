@@ -0,0 +1,421 @@
+//! Interval arithmetic with MPFR endpoints.
+//!
+//! Lower endpoints are always rounded down (`MPFR_RNDD`) and upper ones up (`MPFR_RNDU`), so
+//! the result of every operation contains all results of the operation on points of its
+//! arguments. Endpoints may be infinite; the empty interval has NaN endpoints. Results have
+//! the precision of the first argument.
+
+use libc::{c_int, c_ulong};
+
+use super::{mpfr_prec_t, mpfr_rnd_t, MPFR_RNDD, MPFR_RNDU, MPFR_RNDN};
+use float::{Mpfr, Fn1};
+use inline::{mpfr_get_exp, mpfr_regular_p};
+
+// Zero times anything is zero here, infinities included
+fn mul(x: &Mpfr, y: &Mpfr, prec: mpfr_prec_t, rnd: mpfr_rnd_t) -> Mpfr {
+    if x.sgn() == 0 || y.sgn() == 0 {
+        return special(prec, 0);
+    }
+    x.apply2(super::mpfr_mul, y, prec, rnd)
+}
+
+fn le(x: &Mpfr, y: &Mpfr) -> bool {
+    unsafe { super::mpfr_lessequal_p(x.as_ptr(), y.as_ptr()) != 0 }
+}
+
+fn min(x: Mpfr, y: Mpfr) -> Mpfr {
+    if le(&x, &y) { x } else { y }
+}
+
+fn max(x: Mpfr, y: Mpfr) -> Mpfr {
+    if le(&x, &y) { y } else { x }
+}
+
+// +0 for sign 0, otherwise an infinity of the given sign
+fn special(prec: mpfr_prec_t, sign: c_int) -> Mpfr {
+    let mut r = Mpfr::new(prec);
+    unsafe {
+        if sign == 0 { super::mpfr_set_zero(r.as_mut_ptr(), 1) }
+        else { super::mpfr_set_inf(r.as_mut_ptr(), sign) }
+    }
+    r
+}
+
+pub struct Interval {
+    lo: Mpfr,
+    hi: Mpfr
+}
+
+impl Interval {
+    // [lo, hi] with the endpoints rounded outwards to `prec` bits; panics if lo > hi
+    pub fn new(lo: &Mpfr, hi: &Mpfr, prec: mpfr_prec_t) -> Interval {
+        assert!(le(lo, hi), "lower endpoint is greater than the upper one");
+        Interval { lo: lo.apply(super::mpfr_set, prec, MPFR_RNDD),
+                   hi: hi.apply(super::mpfr_set, prec, MPFR_RNDU) }
+    }
+
+    pub fn point(x: &Mpfr, prec: mpfr_prec_t) -> Interval {
+        Interval::new(x, x, prec)
+    }
+
+    pub fn from_f64(lo: f64, hi: f64, prec: mpfr_prec_t) -> Interval {
+        Interval::new(&Mpfr::from_f64(lo, 53), &Mpfr::from_f64(hi, 53), prec)
+    }
+
+    pub fn entire(prec: mpfr_prec_t) -> Interval {
+        Interval { lo: special(prec, -1), hi: special(prec, 1) }
+    }
+
+    pub fn empty(prec: mpfr_prec_t) -> Interval {
+        Interval { lo: Mpfr::new(prec), hi: Mpfr::new(prec) }
+    }
+
+    #[inline]
+    pub fn lo(&self) -> &Mpfr { &self.lo }
+
+    #[inline]
+    pub fn hi(&self) -> &Mpfr { &self.hi }
+
+    #[inline]
+    pub fn prec(&self) -> mpfr_prec_t { self.lo.prec() }
+
+    pub fn is_empty(&self) -> bool {
+        self.lo.is_nan()
+    }
+
+    pub fn contains(&self, x: &Mpfr) -> bool {
+        !self.is_empty() && le(&self.lo, x) && le(x, &self.hi)
+    }
+
+    pub fn contains_zero(&self) -> bool {
+        !self.is_empty() && self.lo.sgn() <= 0 && self.hi.sgn() >= 0
+    }
+
+    // Whether every point of self is in other; the empty interval is a subset of any other
+    pub fn is_subset(&self, other: &Interval) -> bool {
+        self.is_empty() || (!other.is_empty() && le(&other.lo, &self.lo) && le(&self.hi, &other.hi))
+    }
+
+    // The smallest interval containing both
+    pub fn hull(&self, other: &Interval) -> Interval {
+        let prec = self.prec();
+        if other.is_empty() {
+            return self.clone();
+        }
+        if self.is_empty() {
+            return Interval::new(&other.lo, &other.hi, prec);
+        }
+        Interval { lo: self.lo.apply2(super::mpfr_min, &other.lo, prec, MPFR_RNDD),
+                   hi: self.hi.apply2(super::mpfr_max, &other.hi, prec, MPFR_RNDU) }
+    }
+
+    pub fn intersection(&self, other: &Interval) -> Interval {
+        let prec = self.prec();
+        if self.is_empty() || other.is_empty() {
+            return Interval::empty(prec);
+        }
+        let lo = self.lo.apply2(super::mpfr_max, &other.lo, prec, MPFR_RNDD);
+        let hi = self.hi.apply2(super::mpfr_min, &other.hi, prec, MPFR_RNDU);
+        if le(&lo, &hi) { Interval { lo: lo, hi: hi } } else { Interval::empty(prec) }
+    }
+
+    pub fn add(&self, other: &Interval) -> Interval {
+        let prec = self.prec();
+        if self.is_empty() || other.is_empty() {
+            return Interval::empty(prec);
+        }
+        Interval { lo: self.lo.apply2(super::mpfr_add, &other.lo, prec, MPFR_RNDD),
+                   hi: self.hi.apply2(super::mpfr_add, &other.hi, prec, MPFR_RNDU) }
+    }
+
+    pub fn sub(&self, other: &Interval) -> Interval {
+        let prec = self.prec();
+        if self.is_empty() || other.is_empty() {
+            return Interval::empty(prec);
+        }
+        Interval { lo: self.lo.apply2(super::mpfr_sub, &other.hi, prec, MPFR_RNDD),
+                   hi: self.hi.apply2(super::mpfr_sub, &other.lo, prec, MPFR_RNDU) }
+    }
+
+    pub fn mul(&self, other: &Interval) -> Interval {
+        let prec = self.prec();
+        if self.is_empty() || other.is_empty() {
+            return Interval::empty(prec);
+        }
+        let (a, b, c, d) = (&self.lo, &self.hi, &other.lo, &other.hi);
+        let lo = min(min(mul(a, c, prec, MPFR_RNDD), mul(a, d, prec, MPFR_RNDD)),
+                     min(mul(b, c, prec, MPFR_RNDD), mul(b, d, prec, MPFR_RNDD)));
+        let hi = max(max(mul(a, c, prec, MPFR_RNDU), mul(a, d, prec, MPFR_RNDU)),
+                     max(mul(b, c, prec, MPFR_RNDU), mul(b, d, prec, MPFR_RNDU)));
+        Interval { lo: lo, hi: hi }
+    }
+
+    // Division by an interval containing zero gives the hull of the possible results, which
+    // is a half-line when zero is an endpoint of the divisor and the dividend does not
+    // contain zero, the whole line otherwise; division by [0, 0] gives the empty interval.
+    pub fn div(&self, other: &Interval) -> Interval {
+        let prec = self.prec();
+        if self.is_empty() || other.is_empty() || (other.lo.sgn() == 0 && other.hi.sgn() == 0) {
+            return Interval::empty(prec);
+        }
+        let (a, b, c, d) = (&self.lo, &self.hi, &other.lo, &other.hi);
+        let div = super::mpfr_div;
+
+        if !other.contains_zero() {
+            let down = [a.apply2(div, c, prec, MPFR_RNDD), a.apply2(div, d, prec, MPFR_RNDD),
+                        b.apply2(div, c, prec, MPFR_RNDD), b.apply2(div, d, prec, MPFR_RNDD)];
+            let up = [a.apply2(div, c, prec, MPFR_RNDU), a.apply2(div, d, prec, MPFR_RNDU),
+                      b.apply2(div, c, prec, MPFR_RNDU), b.apply2(div, d, prec, MPFR_RNDU)];
+            // Infinity divided by infinity
+            if down.iter().chain(up.iter()).any(|q| q.is_nan()) {
+                return Interval::entire(prec);
+            }
+            let lo = down.iter().skip(1).fold(&down[0], |m, q| if le(m, q) { m } else { q });
+            let hi = up.iter().skip(1).fold(&up[0], |m, q| if le(q, m) { m } else { q });
+            return Interval { lo: lo.clone(), hi: hi.clone() };
+        }
+
+        if self.contains_zero() || (c.sgn() < 0 && d.sgn() > 0) {
+            Interval::entire(prec)
+        } else if c.sgn() == 0 {
+            if b.sgn() < 0 {
+                Interval { lo: special(prec, -1), hi: b.apply2(div, d, prec, MPFR_RNDU) }
+            } else {
+                Interval { lo: a.apply2(div, d, prec, MPFR_RNDD), hi: special(prec, 1) }
+            }
+        } else {
+            if b.sgn() < 0 {
+                Interval { lo: b.apply2(div, c, prec, MPFR_RNDD), hi: special(prec, 1) }
+            } else {
+                Interval { lo: special(prec, -1), hi: a.apply2(div, c, prec, MPFR_RNDU) }
+            }
+        }
+    }
+
+    // Square root of the non-negative part
+    pub fn sqrt(&self) -> Interval {
+        let prec = self.prec();
+        if self.is_empty() || self.hi.sgn() < 0 {
+            return Interval::empty(prec);
+        }
+        let lo = if self.lo.sgn() <= 0 { special(prec, 0) }
+                 else { self.lo.apply(super::mpfr_sqrt, prec, MPFR_RNDD) };
+        Interval { lo: lo, hi: self.hi.apply(super::mpfr_sqrt, prec, MPFR_RNDU) }
+    }
+
+    pub fn exp(&self) -> Interval {
+        let prec = self.prec();
+        if self.is_empty() {
+            return Interval::empty(prec);
+        }
+        Interval { lo: self.lo.apply(super::mpfr_exp, prec, MPFR_RNDD),
+                   hi: self.hi.apply(super::mpfr_exp, prec, MPFR_RNDU) }
+    }
+
+    // Logarithm of the positive part
+    pub fn log(&self) -> Interval {
+        let prec = self.prec();
+        if self.is_empty() || self.hi.sgn() < 0 {
+            return Interval::empty(prec);
+        }
+        let lo = if self.lo.sgn() <= 0 { special(prec, -1) }
+                 else { self.lo.apply(super::mpfr_log, prec, MPFR_RNDD) };
+        Interval { lo: lo, hi: self.hi.apply(super::mpfr_log, prec, MPFR_RNDU) }
+    }
+
+    pub fn sin(&self) -> Interval {
+        self.trig(super::mpfr_sin, true)
+    }
+
+    pub fn cos(&self) -> Interval {
+        self.trig(super::mpfr_cos, false)
+    }
+
+    // Both functions are monotonic between their extrema, which are (-1)^k at k*pi for cos
+    // and at pi/2 + k*pi for sin. The range is spanned by the values at the endpoints and
+    // at the extrema inside the interval; the latter are found with an enclosure of pi, so
+    // some extra ones may be included, which only widens the result.
+    fn trig(&self, f: Fn1, half: bool) -> Interval {
+        let prec = self.prec();
+        if self.is_empty() {
+            return Interval::empty(prec);
+        }
+        let whole = Interval { lo: Mpfr::from_si(-1, prec), hi: Mpfr::from_si(1, prec) };
+        if unsafe { super::mpfr_number_p(self.lo.as_ptr()) == 0 ||
+                    super::mpfr_number_p(self.hi.as_ptr()) == 0 } {
+            return whole;
+        }
+
+        let wp = prec + 32;
+        let mut pi_lo = Mpfr::new(wp);
+        let mut pi_hi = Mpfr::new(wp);
+        unsafe {
+            super::mpfr_const_pi(pi_lo.as_mut_ptr(), MPFR_RNDD);
+            super::mpfr_const_pi(pi_hi.as_mut_ptr(), MPFR_RNDU);
+        }
+        // Bounds of the index k of an extremum at x: x/pi for cos, x/pi - 1/2 for sin
+        let index = |x: &Mpfr, rnd: mpfr_rnd_t| -> Mpfr {
+            let down = rnd == MPFR_RNDD;
+            let pi = if (x.sgn() >= 0) == down { &pi_hi } else { &pi_lo };
+            let mut q = x.apply2(super::mpfr_div, pi, wp, rnd);
+            if half {
+                let h = Mpfr::from_f64(0.5, wp);
+                q = q.apply2(super::mpfr_sub, &h, wp, rnd);
+            }
+            q
+        };
+        let mut kmin = Mpfr::new(wp);
+        let mut kmax = Mpfr::new(wp);
+        unsafe {
+            super::mpfr_ceil(kmin.as_mut_ptr(), index(&self.lo, MPFR_RNDD).as_ptr());
+            super::mpfr_floor(kmax.as_mut_ptr(), index(&self.hi, MPFR_RNDU).as_ptr());
+        }
+
+        let lo = min(self.lo.apply(f, prec, MPFR_RNDD), self.hi.apply(f, prec, MPFR_RNDD));
+        let hi = max(self.lo.apply(f, prec, MPFR_RNDU), self.hi.apply(f, prec, MPFR_RNDU));
+        if !le(&kmin, &kmax) {
+            return Interval { lo: lo, hi: hi };
+        }
+        let n = kmax.apply2(super::mpfr_sub, &kmin, wp, MPFR_RNDU);
+        // Parity is meaningless once integers are not exact at working precision
+        let huge = |k: &Mpfr| unsafe {
+            mpfr_regular_p(k.as_ptr()) != 0 && mpfr_get_exp(k.as_ptr()) as mpfr_prec_t >= wp - 1
+        };
+        if !le(&n, &Mpfr::from_si(1, wp)) || huge(&kmin) || huge(&kmax) {
+            return whole;
+        }
+
+        let mut result = Interval { lo: lo, hi: hi };
+        for k in [&kmin, &kmax].iter() {
+            let mut half_k = Mpfr::new(wp);
+            let even = unsafe {
+                super::mpfr_div_2ui(half_k.as_mut_ptr(), k.as_ptr(), 1 as c_ulong, MPFR_RNDN);
+                super::mpfr_integer_p(half_k.as_ptr()) != 0
+            };
+            if even {
+                result.hi = Mpfr::from_si(1, prec)
+            } else {
+                result.lo = Mpfr::from_si(-1, prec)
+            }
+        }
+        result
+    }
+}
+
+impl Clone for Interval {
+    fn clone(&self) -> Interval {
+        Interval { lo: self.lo.clone(), hi: self.hi.clone() }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use std::f64;
+    use std::num::Float;
+
+    use super::Interval;
+    use super::super::{mpfr_prec_t, MPFR_RNDN};
+    use float::{Mpfr, Fn2};
+
+    fn iv(lo: f64, hi: f64) -> Interval {
+        Interval::from_f64(lo, hi, 53)
+    }
+
+    // The endpoints, exact at 53 bits
+    fn ends(x: &Interval) -> (f64, f64) {
+        (x.lo().to_f64(MPFR_RNDN), x.hi().to_f64(MPFR_RNDN))
+    }
+
+    // Points spread over [lo, hi], ends included
+    fn points(x: &Interval) -> Vec<Mpfr> {
+        let (lo, hi) = ends(x);
+        (0..9).map(|i| Mpfr::from_f64(lo + (hi - lo) * i as f64 / 8.0, 53)).collect()
+    }
+
+    // f applied to points of x and y, with many more bits than the endpoints
+    fn contains_all<G>(x: &Interval, y: &Interval, f: Fn2, g: G) -> bool
+        where G: Fn(&Interval, &Interval) -> Interval
+    {
+        let prec: mpfr_prec_t = 200;
+        let r = g(x, y);
+        points(x).iter().all(|p| points(y).iter().all(|q| {
+            // 0 / 0 has no value to contain
+            let v = p.apply2(f, q, prec, MPFR_RNDN);
+            v.is_nan() || r.contains(&v)
+        }))
+    }
+
+    #[test]
+    fn operations_contain_point_results() {
+        let xs = [iv(-3.0, -0.1), iv(-1.5, 2.5), iv(0.1, 0.3), iv(1e10, 1e20)];
+        for x in xs.iter() {
+            for y in xs.iter() {
+                assert!(contains_all(x, y, super::super::mpfr_add,
+                                     |x: &Interval, y: &Interval| x.add(y)));
+                assert!(contains_all(x, y, super::super::mpfr_sub,
+                                     |x: &Interval, y: &Interval| x.sub(y)));
+                assert!(contains_all(x, y, super::super::mpfr_mul,
+                                     |x: &Interval, y: &Interval| x.mul(y)));
+                assert!(contains_all(x, y, super::super::mpfr_div,
+                                     |x: &Interval, y: &Interval| x.div(y)));
+            }
+        }
+        // 1/3 is rounded outwards
+        let third = iv(1.0, 1.0).div(&iv(3.0, 3.0));
+        let (lo, hi) = ends(&third);
+        assert!(lo < hi && lo <= 1.0 / 3.0 && 1.0 / 3.0 <= hi);
+    }
+
+    #[test]
+    fn division_by_zero() {
+        assert_eq!(ends(&iv(1.0, 2.0).div(&iv(0.0, 4.0))), (0.25, f64::INFINITY));
+        assert_eq!(ends(&iv(1.0, 2.0).div(&iv(-4.0, 0.0))), (-f64::INFINITY, -0.25));
+        assert_eq!(ends(&iv(-2.0, -1.0).div(&iv(0.0, 4.0))), (-f64::INFINITY, -0.25));
+        assert_eq!(ends(&iv(-2.0, -1.0).div(&iv(-4.0, 0.0))), (0.25, f64::INFINITY));
+        assert_eq!(ends(&iv(1.0, 2.0).div(&iv(-1.0, 1.0))), (-f64::INFINITY, f64::INFINITY));
+        assert_eq!(ends(&iv(-1.0, 2.0).div(&iv(0.0, 1.0))), (-f64::INFINITY, f64::INFINITY));
+        assert!(iv(1.0, 2.0).div(&iv(0.0, 0.0)).is_empty());
+    }
+
+    #[test]
+    fn sin_and_cos() {
+        let (s1, s2, c1) = (1.0f64.sin(), 2.0f64.sin(), 1.0f64.cos());
+        // sin reaches 1 at pi/2
+        let s = iv(1.0, 2.0).sin();
+        assert_eq!(s.hi().to_f64(MPFR_RNDN), 1.0);
+        assert!(s.lo().to_f64(MPFR_RNDN) <= s1.min(s2));
+        assert!(s.lo().to_f64(MPFR_RNDN) > s1.min(s2) - 1e-15);
+        // cos reaches 1 at 0 and -1 at pi
+        let c = iv(-1.0, 1.0).cos();
+        assert_eq!(c.hi().to_f64(MPFR_RNDN), 1.0);
+        assert!((c.lo().to_f64(MPFR_RNDN) - c1).abs() < 1e-15);
+        assert_eq!(iv(3.0, 4.0).cos().lo().to_f64(MPFR_RNDN), -1.0);
+        // Monotonic pieces and whole periods
+        let (lo, hi) = ends(&iv(0.1, 0.2).sin());
+        assert!(lo <= 0.1f64.sin() && 0.2f64.sin() <= hi && hi < 0.2f64.sin() + 1e-15);
+        assert_eq!(ends(&iv(0.0, 7.0).sin()), (-1.0, 1.0));
+        assert_eq!(ends(&iv(-f64::INFINITY, 0.0).cos()), (-1.0, 1.0));
+    }
+
+    #[test]
+    fn hull_and_intersection() {
+        assert_eq!(ends(&iv(0.0, 1.0).hull(&iv(2.0, 3.0))), (0.0, 3.0));
+        assert_eq!(ends(&Interval::empty(53).hull(&iv(2.0, 3.0))), (2.0, 3.0));
+        assert_eq!(ends(&iv(0.0, 1.0).hull(&Interval::empty(53))), (0.0, 1.0));
+        assert_eq!(ends(&iv(0.0, 2.0).intersection(&iv(1.0, 3.0))), (1.0, 2.0));
+        assert_eq!(ends(&iv(0.0, 1.0).intersection(&iv(1.0, 3.0))), (1.0, 1.0));
+        assert!(iv(0.0, 1.0).intersection(&iv(2.0, 3.0)).is_empty());
+        assert!(iv(1.0, 2.0).is_subset(&iv(0.0, 3.0)));
+        assert!(!iv(0.0, 3.0).is_subset(&iv(1.0, 2.0)));
+        assert!(Interval::empty(53).is_subset(&iv(1.0, 2.0)));
+    }
+
+    #[test]
+    fn sqrt_and_log_of_partly_negative_intervals() {
+        assert_eq!(ends(&iv(-1.0, 4.0).sqrt()), (0.0, 2.0));
+        assert!(iv(-2.0, -1.0).sqrt().is_empty());
+        assert_eq!(iv(-1.0, 1.0).log().lo().to_f64(MPFR_RNDN), -f64::INFINITY);
+        assert_eq!(iv(-1.0, 1.0).log().hi().to_f64(MPFR_RNDN), 0.0);
+    }
+}
@@ -5,6 +5,44 @@ extern crate "gmp-sys" as gmp;
 use libc::{size_t, intmax_t, uintmax_t};
 use gmp::{mp_limb_t, gmp_randstate_t, mpf_ptr, mpz_ptr, mpz_srcptr, mpq_srcptr, mpf_srcptr};
 
+pub mod inline;
+pub mod custom;
+pub mod arena;
+pub mod memory;
+pub mod context;
+pub mod float;
+pub mod special;
+pub mod paired;
+pub mod sum;
+pub mod linalg;
+pub mod ziv;
+pub mod interval;
+pub mod ball;
+pub mod complex;
+pub mod poly;
+pub mod remez;
+pub mod ulp;
+pub mod cr;
+
+// MPFR_VERSION_MAJOR, MPFR_VERSION_MINOR, MPFR_VERSION_PATCHLEVEL, MPFR_VERSION_STRING,
+// MPFR_PREC_MIN, MPFR_PREC_MAX, MPFR_EMIN_DEFAULT, MPFR_EMAX_DEFAULT and the exponent values
+// reserved for special numbers (__MPFR_EXP_MAX, __MPFR_EXP_NAN, __MPFR_EXP_ZERO and
+// __MPFR_EXP_INF) as defined in mpfr.h the crate is built against
+include!(concat!(env!("OUT_DIR"), "/mpfr_consts.rs"));
+
+pub const MPFR_VERSION: ::libc::c_long = ((MPFR_VERSION_MAJOR as ::libc::c_long) << 16) |
+                                         ((MPFR_VERSION_MINOR as ::libc::c_long) << 8) |
+                                         (MPFR_VERSION_PATCHLEVEL as ::libc::c_long);
+
+// Compares the version of the header the crate was built against with the version of
+// the library loaded at run time; they differ when e.g. the system library was upgraded.
+// Returns the version of the library in case of mismatch.
+pub fn check_version() -> Result<(), String> {
+    let version = unsafe { ::std::ffi::c_str_to_bytes(&mpfr_get_version()) };
+    let version = String::from_utf8_lossy(version).into_owned();
+    if version == MPFR_VERSION_STRING { Ok(()) } else { Err(version) }
+}
+
 /* automatically generated by rust-bindgen */
 
 pub type mpfr_void = ::libc::c_void;
@@ -22,11 +60,9 @@ pub const MPFR_RNDA: ::libc::c_int = 4;
 pub const MPFR_RNDF: ::libc::c_int = 5;
 pub const MPFR_RNDNA: ::libc::c_int = -1;
 pub type mpfr_rnd_t = Enum_Unnamed1;
-pub type mpfr_prec_t = ::libc::c_long;
-pub type mpfr_uprec_t = ::libc::c_ulong;
-pub type mpfr_sign_t = ::libc::c_int;
-pub type mpfr_exp_t = ::libc::c_long;
-pub type mpfr_uexp_t = ::libc::c_ulong;
+// mpfr_prec_t, mpfr_uprec_t, mpfr_sign_t, mpfr_exp_t and mpfr_uexp_t, selected by build.rs
+// according to the formats mpfr.h was configured with
+include!(concat!(env!("OUT_DIR"), "/mpfr_types.rs"));
 #[repr(C)]
 #[derive(Copy)]
 pub struct Struct_Unnamed2 {
@@ -547,3 +583,51 @@ extern "C" {
     pub fn __gmpfr_mpfr_get_uj(arg1: mpfr_srcptr, arg2: mpfr_rnd_t)
      -> uintmax_t;
 }
+
+#[cfg(test)]
+mod test {
+    use std::mem;
+    use super::{__mpfr_struct, check_version};
+    use super::{MPFR_EMIN_DEFAULT, MPFR_EMAX_DEFAULT, MPFR_PREC_MIN, MPFR_PREC_MAX};
+    use super::{__MPFR_EXP_NAN, __MPFR_EXP_ZERO, __MPFR_EXP_INF};
+
+    // C_SIZE and C_OFFSETS, the layout of __mpfr_struct reported by the C compiler
+    include!(concat!(env!("OUT_DIR"), "/mpfr_layout.rs"));
+
+    #[test]
+    fn struct_layout_matches_c() {
+        let x: __mpfr_struct = Default::default();
+        let base = &x as *const __mpfr_struct as usize;
+        let offsets = [&x._mpfr_prec as *const _ as usize - base,
+                       &x._mpfr_sign as *const _ as usize - base,
+                       &x._mpfr_exp as *const _ as usize - base,
+                       &x._mpfr_d as *const _ as usize - base];
+        assert_eq!(mem::size_of::<__mpfr_struct>(), C_SIZE);
+        assert_eq!(offsets, C_OFFSETS);
+    }
+
+    #[test]
+    fn version_matches_the_library() {
+        assert_eq!(check_version(), Ok(()));
+    }
+
+    #[test]
+    fn constants_match_the_library() {
+        unsafe {
+            // A new thread starts with the default exponent range
+            assert_eq!(super::mpfr_get_emin(), MPFR_EMIN_DEFAULT);
+            assert_eq!(super::mpfr_get_emax(), MPFR_EMAX_DEFAULT);
+            assert!(0 < MPFR_PREC_MIN && MPFR_PREC_MIN < MPFR_PREC_MAX);
+
+            let mut x: __mpfr_struct = Default::default();
+            super::mpfr_init2(&mut x, MPFR_PREC_MIN);
+            super::mpfr_set_nan(&mut x);
+            assert_eq!(x._mpfr_exp, __MPFR_EXP_NAN);
+            super::mpfr_set_zero(&mut x, 1);
+            assert_eq!(x._mpfr_exp, __MPFR_EXP_ZERO);
+            super::mpfr_set_inf(&mut x, 1);
+            assert_eq!(x._mpfr_exp, __MPFR_EXP_INF);
+            super::mpfr_clear(&mut x);
+        }
+    }
+}
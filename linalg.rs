@@ -0,0 +1,222 @@
+//! Correctly rounded dot products and matrix kernels.
+//!
+//! Each result entry is rounded once: products are computed exactly (with a precision equal
+//! to the sum of the precisions of the factors) and added with `mpfr_sum`, or, for short
+//! vectors, combined with a single `mpfr_mul` or `mpfr_fma`. This makes them suitable as
+//! reference results for floating-point BLAS kernels. Matrices are dense and row-major.
+//!
+//! Exactness of the products holds as long as they stay within the current exponent range.
+//!
+//! Rounding such a result to f64 would round twice, and give a wrong subnormal or miss an
+//! overflow; the `_f64` variants instead compute each entry exactly and round it once to
+//! binary64 with `cr::Format`.
+
+use libc::c_double;
+use gmp::mp_limb_t;
+
+use super::{mpfr_srcptr, mpfr_prec_t, mpfr_rnd_t, MPFR_RNDN};
+use float::Mpfr;
+use custom::InlineFloat;
+use inline::mpfr_get_prec;
+use sum::sum_exact;
+use cr::Format;
+
+// Vector and matrix entries
+pub trait Entry {
+    // Calls `f` with the entry as an MPFR value
+    fn with_raw<R, F: FnOnce(mpfr_srcptr) -> R>(&self, f: F) -> R;
+}
+
+impl Entry for Mpfr {
+    #[inline]
+    fn with_raw<R, F: FnOnce(mpfr_srcptr) -> R>(&self, f: F) -> R {
+        f(self.as_ptr())
+    }
+}
+
+// Converted exactly to a 53-bit value without allocation
+impl Entry for f64 {
+    fn with_raw<R, F: FnOnce(mpfr_srcptr) -> R>(&self, f: F) -> R {
+        let mut v: InlineFloat<[mp_limb_t; 2]> = InlineFloat::new(53);
+        unsafe { super::mpfr_set_d(v.as_mut_ptr(), *self as c_double, MPFR_RNDN) };
+        f(v.as_ptr())
+    }
+}
+
+fn exact_product<T: Entry>(x: &T, y: &T) -> Mpfr {
+    x.with_raw(|px| y.with_raw(|py| {
+        let mut r = unsafe { Mpfr::new(mpfr_get_prec(px) + mpfr_get_prec(py)) };
+        unsafe { super::mpfr_mul(r.as_mut_ptr(), px, py, MPFR_RNDN) };
+        r
+    }))
+}
+
+// Sum of x[f(i)] * y[g(i)] for i in 0..n, rounded once
+fn dot_by<T: Entry, F: Fn(usize) -> usize, G: Fn(usize) -> usize>(
+    x: &[T], y: &[T], n: usize, f: F, g: G, prec: mpfr_prec_t, rnd: mpfr_rnd_t) -> (Mpfr, bool) {
+    match n {
+        1 => {
+            let mut r = Mpfr::new(prec);
+            let t = x[f(0)].with_raw(|px| y[g(0)].with_raw(|py| unsafe {
+                super::mpfr_mul(r.as_mut_ptr(), px, py, rnd)
+            }));
+            (r, t == 0)
+        }
+        2 => {
+            let p = exact_product(&x[f(1)], &y[g(1)]);
+            let mut r = Mpfr::new(prec);
+            let t = x[f(0)].with_raw(|px| y[g(0)].with_raw(|py| unsafe {
+                super::mpfr_fma(r.as_mut_ptr(), px, py, p.as_ptr(), rnd)
+            }));
+            (r, t == 0)
+        }
+        _ => {
+            // An empty sum gives +0
+            let products: Vec<Mpfr> = (0..n).map(|i| exact_product(&x[f(i)], &y[g(i)])).collect();
+            sum_exact(products.as_slice(), prec, rnd)
+        }
+    }
+}
+
+// Returns the dot product of `x` and `y` correctly rounded to `prec` bits, and whether it
+// is exact
+pub fn dot<T: Entry>(x: &[T], y: &[T], prec: mpfr_prec_t, rnd: mpfr_rnd_t) -> (Mpfr, bool) {
+    assert!(x.len() == y.len(), "vectors of different lengths {} and {}", x.len(), y.len());
+    dot_by(x, y, x.len(), |i| i, |i| i, prec, rnd)
+}
+
+// Products of f64 values are exact with 106 bits and lie between 2^-2148 and 2^2048 in
+// magnitude, so this precision holds their sum exactly for up to 2^100 terms
+const EXACT_PREC: mpfr_prec_t = 4300;
+
+fn dot_by_f64<F: Fn(usize) -> usize, G: Fn(usize) -> usize>(
+    x: &[f64], y: &[f64], n: usize, f: F, g: G, rnd: mpfr_rnd_t) -> f64 {
+    let (r, exact) = dot_by(x, y, n, f, g, EXACT_PREC, MPFR_RNDN);
+    debug_assert!(exact || r.is_nan());
+    Format::Binary64.round(&r, rnd)
+}
+
+// The dot product of `x` and `y` correctly rounded to f64, subnormals and overflow included
+pub fn dot_f64(x: &[f64], y: &[f64], rnd: mpfr_rnd_t) -> f64 {
+    assert!(x.len() == y.len(), "vectors of different lengths {} and {}", x.len(), y.len());
+    dot_by_f64(x, y, x.len(), |i| i, |i| i, rnd)
+}
+
+// A * x for an m by n matrix A, every entry correctly rounded to `prec` bits
+pub fn gemv<T: Entry>(a: &[T], m: usize, n: usize, x: &[T],
+                      prec: mpfr_prec_t, rnd: mpfr_rnd_t) -> Vec<Mpfr> {
+    assert!(a.len() == m * n, "matrix has {} entries instead of {}", a.len(), m * n);
+    assert!(x.len() == n, "vector has {} entries instead of {}", x.len(), n);
+    (0..m).map(|i| dot_by(a, x, n, |l| i * n + l, |l| l, prec, rnd).0).collect()
+}
+
+// A * B for an m by k matrix A and a k by n matrix B, every entry of the m by n result
+// correctly rounded to `prec` bits
+pub fn gemm<T: Entry>(a: &[T], b: &[T], m: usize, k: usize, n: usize,
+                      prec: mpfr_prec_t, rnd: mpfr_rnd_t) -> Vec<Mpfr> {
+    assert!(a.len() == m * k, "matrix A has {} entries instead of {}", a.len(), m * k);
+    assert!(b.len() == k * n, "matrix B has {} entries instead of {}", b.len(), k * n);
+    let mut c = Vec::with_capacity(m * n);
+    for i in 0..m {
+        for j in 0..n {
+            c.push(dot_by(a, b, k, |l| i * k + l, |l| l * n + j, prec, rnd).0);
+        }
+    }
+    c
+}
+
+// gemv with every entry correctly rounded to f64
+pub fn gemv_f64(a: &[f64], m: usize, n: usize, x: &[f64], rnd: mpfr_rnd_t) -> Vec<f64> {
+    assert!(a.len() == m * n, "matrix has {} entries instead of {}", a.len(), m * n);
+    assert!(x.len() == n, "vector has {} entries instead of {}", x.len(), n);
+    (0..m).map(|i| dot_by_f64(a, x, n, |l| i * n + l, |l| l, rnd)).collect()
+}
+
+// gemm with every entry correctly rounded to f64
+pub fn gemm_f64(a: &[f64], b: &[f64], m: usize, k: usize, n: usize, rnd: mpfr_rnd_t)
+                -> Vec<f64> {
+    assert!(a.len() == m * k, "matrix A has {} entries instead of {}", a.len(), m * k);
+    assert!(b.len() == k * n, "matrix B has {} entries instead of {}", b.len(), k * n);
+    let mut c = Vec::with_capacity(m * n);
+    for i in 0..m {
+        for j in 0..n {
+            c.push(dot_by_f64(a, b, k, |l| i * k + l, |l| l * n + j, rnd));
+        }
+    }
+    c
+}
+
+#[cfg(test)]
+mod test {
+    use std::f64;
+    use std::num::Float;
+
+    use super::{dot, gemv, gemm, dot_f64, gemv_f64, gemm_f64};
+    use super::super::{MPFR_RNDN, MPFR_RNDZ, MPFR_RNDD};
+    use float::Mpfr;
+
+    fn p(e: i32) -> f64 {
+        2.0f64.powi(e)
+    }
+
+    fn to_f64(v: Vec<Mpfr>) -> Vec<f64> {
+        v.iter().map(|x| x.to_f64(MPFR_RNDN)).collect()
+    }
+
+    #[test]
+    fn cancellation() {
+        let ones = [1.0, 1.0, 1.0];
+        let (r, exact) = dot(&[1e308, 1.0, -1e308], &ones, 53, MPFR_RNDN);
+        assert!(r.to_f64(MPFR_RNDN) == 1.0 && exact);
+        assert_eq!(dot_f64(&[1e308, 1.0, -1e308], &ones, MPFR_RNDN), 1.0);
+
+        // Products 2^1000, 2^-1000 and -2^1000, far outside the range of f64 together
+        let (x, y) = ([p(600), p(-600), p(600)], [p(400), p(-400), -p(400)]);
+        let (r, exact) = dot(&x, &y, 53, MPFR_RNDN);
+        assert!(r.to_f64(MPFR_RNDN) == p(-1000) && exact);
+        assert_eq!(dot_f64(&x, &y, MPFR_RNDN), p(-1000));
+
+        // 1 + 2^-53 + 2^-106 is above the midpoint of 1 and 1 + 2^-52
+        let (x, y) = ([1.0, p(-53), p(-53)], [1.0, 1.0, p(-53)]);
+        assert_eq!(dot_f64(&x, &y, MPFR_RNDN), 1.0 + p(-52));
+        assert_eq!(dot_f64(&x, &y, MPFR_RNDZ), 1.0);
+
+        // Exact zero, from both the fma and the mpfr_sum paths
+        assert_eq!(dot_f64(&[3.0, 3.0], &[0.1, -0.1], MPFR_RNDN), 0.0);
+        // The f64 values of 0.1 + 0.2 - 0.3 add up to 2^-55
+        assert_eq!(dot_f64(&[0.1, 0.2, 0.3], &[3.0, 3.0, -3.0], MPFR_RNDN), 3.0 * p(-55));
+        assert_eq!(dot_f64(&[], &[], MPFR_RNDN), 0.0);
+    }
+
+    #[test]
+    fn subnormal_and_overflowing_results() {
+        // 2^-1075 + 2^-1140 rounds to the smallest subnormal, but to half of it with 53 bits
+        let y = [p(-538), p(-570), 1.0];
+        for x in [vec![p(-537), p(-570)], vec![p(-537), p(-570), 0.0]].iter() {
+            let y = &y[..x.len()];
+            assert_eq!(dot_f64(&**x, y, MPFR_RNDN), p(-1074));
+            assert_eq!(dot(&**x, y, 53, MPFR_RNDN).0.to_f64(MPFR_RNDN), 0.0);
+        }
+
+        assert_eq!(dot_f64(&[1e308], &[10.0], MPFR_RNDN), f64::INFINITY);
+        assert_eq!(dot_f64(&[1e308], &[10.0], MPFR_RNDZ), f64::MAX_VALUE);
+        assert_eq!(dot_f64(&[1e308, 1e308], &[10.0, -9.0], MPFR_RNDN), 1e308);
+        assert_eq!(dot_f64(&[-1e-300, 1e-300], &[1e-300, 0.0], MPFR_RNDD), -p(-1074));
+    }
+
+    #[test]
+    fn matrices() {
+        // [1e16 1; 1 -1e16] * [1 1e16; 1 1]
+        let a = [1e16, 1.0, 1.0, -1e16];
+        let b = [1.0, 1e16, 1.0, 1.0];
+        let c = [1e16 + 1.0, 1e32 + 1.0, 1.0 - 1e16, 0.0];
+        assert_eq!(gemm_f64(&a, &b, 2, 2, 2, MPFR_RNDN), c.to_vec());
+        assert_eq!(to_f64(gemm(&a, &b, 2, 2, 2, 53, MPFR_RNDN)), c.to_vec());
+
+        // A 2 by 3 matrix times a 3-vector
+        let a = [1.0, 2.0, 3.0, 1e300, 1.0, -1e300];
+        let x = [1.0, 1.0, 1.0];
+        assert_eq!(gemv_f64(&a, 2, 3, &x, MPFR_RNDN), vec![6.0, 1.0]);
+        assert_eq!(to_f64(gemv(&a, 2, 3, &x, 53, MPFR_RNDN)), vec![6.0, 1.0]);
+    }
+}
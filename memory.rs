@@ -0,0 +1,86 @@
+//! Routing GMP and MPFR memory allocation through the Rust allocator.
+//!
+//! By default GMP (and hence MPFR, which allocates limbs, caches and strings through GMP
+//! memory functions) uses libc malloc. After `use_rust_allocator()` all these allocations
+//! go through the Rust heap, so they show up in its statistics. GMP passes the size of
+//! the block to its reallocation and free functions, so no bookkeeping is needed.
+
+use std::rt::heap;
+use std::intrinsics;
+use std::ffi::c_str_to_bytes;
+use libc::{c_void, c_char, size_t};
+
+extern "C" {
+    fn __gmp_set_memory_functions(alloc: extern "C" fn(size_t) -> *mut c_void,
+                                  realloc: extern "C" fn(*mut c_void, size_t, size_t) -> *mut c_void,
+                                  free: extern "C" fn(*mut c_void, size_t));
+}
+
+// Enough for any type GMP and MPFR keep in allocated memory, like malloc
+const ALIGN: usize = 16;
+
+// GMP may ask for zero bytes, while the Rust allocator needs a real block to free later
+#[inline]
+fn block_size(size: size_t) -> usize {
+    if size == 0 { 1 } else { size as usize }
+}
+
+extern "C" fn rust_alloc(size: size_t) -> *mut c_void {
+    let p = unsafe { heap::allocate(block_size(size), ALIGN) };
+    // Unwinding into C is not an option
+    if p.is_null() { unsafe { intrinsics::abort() } }
+    p as *mut c_void
+}
+
+extern "C" fn rust_realloc(ptr: *mut c_void, old_size: size_t, new_size: size_t) -> *mut c_void {
+    let p = unsafe {
+        heap::reallocate(ptr as *mut u8, block_size(old_size), block_size(new_size), ALIGN)
+    };
+    if p.is_null() { unsafe { intrinsics::abort() } }
+    p as *mut c_void
+}
+
+extern "C" fn rust_free(ptr: *mut c_void, size: size_t) {
+    unsafe { heap::deallocate(ptr as *mut u8, block_size(size), ALIGN) }
+}
+
+// Makes GMP and MPFR allocate memory with the Rust allocator.
+//
+// Unsafe because blocks allocated before the call would be freed with the wrong allocator:
+// it has to be called before any GMP or MPFR value is initialized, including the constant
+// caches of MPFR, typically first thing in main().
+pub unsafe fn use_rust_allocator() {
+    __gmp_set_memory_functions(rust_alloc, rust_realloc, rust_free);
+}
+
+// A string returned by mpfr_get_str() with a null buffer, freed with mpfr_free_str().
+//
+// mpfr_free_str() passes the length of the string to the GMP free function, which is the
+// size it was allocated with, so this works with any memory functions, including the ones
+// installed by use_rust_allocator().
+pub struct MpfrStr {
+    ptr: *mut c_char
+}
+
+impl MpfrStr {
+    // Takes ownership of a string allocated by MPFR
+    pub unsafe fn from_raw(ptr: *mut c_char) -> MpfrStr {
+        assert!(!ptr.is_null());
+        MpfrStr { ptr: ptr }
+    }
+
+    pub fn as_ptr(&self) -> *const c_char { self.ptr as *const c_char }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        unsafe { c_str_to_bytes(&(self.ptr as *const c_char)) }
+    }
+}
+
+// GMP memory functions are not tied to a thread
+unsafe impl Send for MpfrStr {}
+
+impl Drop for MpfrStr {
+    fn drop(&mut self) {
+        unsafe { super::mpfr_free_str(self.ptr) }
+    }
+}
@@ -0,0 +1,163 @@
+//! Safe wrappers for functions producing two results.
+//!
+//! In C these write through several output pointers, which must not alias each other, and
+//! return both ternary values packed in one int. The `_into` variants take the outputs as
+//! distinct `&mut` borrows, the others allocate them; both unpack the ternary values.
+
+use std::cmp::Ordering;
+use libc::{c_int, c_long};
+
+use super::{mpfr_prec_t, mpfr_rnd_t, mpfr_exp_t};
+use float::{Mpfr, ordering};
+
+pub use special::lgamma;
+
+// Splits a ternary value of the form INEX(a, b): 0 for exact, 1 for a rounded result
+// greater than the exact one, 2 for a smaller one; a in bits 0-1 and b in bits 2-3
+fn split_ternary(t: c_int) -> (Ordering, Ordering) {
+    fn decode(code: c_int) -> Ordering {
+        match code {
+            0 => Ordering::Equal,
+            1 => Ordering::Greater,
+            _ => Ordering::Less
+        }
+    }
+    (decode(t & 3), decode((t >> 2) & 3))
+}
+
+macro_rules! paired {
+    ($($name:ident, $name_into:ident => $f:ident),+) => { $(
+        pub fn $name_into(a: &mut Mpfr, b: &mut Mpfr, x: &Mpfr, rnd: mpfr_rnd_t)
+                          -> (Ordering, Ordering) {
+            split_ternary(unsafe { super::$f(a.as_mut_ptr(), b.as_mut_ptr(), x.as_ptr(), rnd) })
+        }
+
+        pub fn $name(x: &Mpfr, prec: mpfr_prec_t, rnd: mpfr_rnd_t)
+                     -> (Mpfr, Mpfr, Ordering, Ordering) {
+            let mut a = Mpfr::new(prec);
+            let mut b = Mpfr::new(prec);
+            let (ta, tb) = $name_into(&mut a, &mut b, x, rnd);
+            (a, b, ta, tb)
+        }
+    )+ }
+}
+
+// (sin x, cos x)
+paired!(sin_cos, sin_cos_into => mpfr_sin_cos);
+
+// (sinh x, cosh x)
+paired!(sinh_cosh, sinh_cosh_into => mpfr_sinh_cosh);
+
+// (integral part, fractional part)
+paired!(modf, modf_into => mpfr_modf);
+
+// x - n*y where n is x/y rounded to the nearest integer, together with the low
+// significant bits of n (at least three) carrying its sign
+pub fn remquo_into(r: &mut Mpfr, x: &Mpfr, y: &Mpfr, rnd: mpfr_rnd_t) -> (c_long, Ordering) {
+    let mut q: c_long = 0;
+    let t = unsafe { super::mpfr_remquo(r.as_mut_ptr(), &mut q, x.as_ptr(), y.as_ptr(), rnd) };
+    (q, ordering(t))
+}
+
+pub fn remquo(x: &Mpfr, y: &Mpfr, prec: mpfr_prec_t, rnd: mpfr_rnd_t)
+              -> (c_long, Mpfr, Ordering) {
+    let mut r = Mpfr::new(prec);
+    let (q, t) = remquo_into(&mut r, x, y, rnd);
+    (q, r, t)
+}
+
+// (e, y) with x = y * 2^e and 0.5 <= |y| < 1; e is 0 for zero and unspecified for Inf
+// and NaN
+pub fn frexp_into(y: &mut Mpfr, x: &Mpfr, rnd: mpfr_rnd_t) -> (mpfr_exp_t, Ordering) {
+    let mut e: mpfr_exp_t = 0;
+    let t = unsafe { super::mpfr_frexp(&mut e, y.as_mut_ptr(), x.as_ptr(), rnd) };
+    (e, ordering(t))
+}
+
+pub fn frexp(x: &Mpfr, prec: mpfr_prec_t, rnd: mpfr_rnd_t) -> (mpfr_exp_t, Mpfr, Ordering) {
+    let mut y = Mpfr::new(prec);
+    let (e, t) = frexp_into(&mut y, x, rnd);
+    (e, y, t)
+}
+
+// (d, e) with d * 2^e equal to x rounded to double and 0.5 <= |d| < 1; e is 0 for zero
+// and unspecified for Inf and NaN
+pub fn get_d_2exp(x: &Mpfr, rnd: mpfr_rnd_t) -> (f64, c_long) {
+    let mut e: c_long = 0;
+    let d = unsafe { super::mpfr_get_d_2exp(&mut e, x.as_ptr(), rnd) };
+    (d as f64, e)
+}
+
+#[cfg(test)]
+mod test {
+    use std::cmp::Ordering;
+    use std::cmp::Ordering::{Less, Equal, Greater};
+
+    use super::{split_ternary, sin_cos, sinh_cosh, modf};
+    use super::super::{mpfr_prec_t, mpfr_rnd_t, MPFR_RNDN, MPFR_RNDZ, MPFR_RNDU, MPFR_RNDD};
+    use float::{Mpfr, Fn1, ordering};
+
+    #[test]
+    fn ternary_values() {
+        assert_eq!(split_ternary(0), (Equal, Equal));
+        assert_eq!(split_ternary(1), (Greater, Equal));
+        assert_eq!(split_ternary(2), (Less, Equal));
+        assert_eq!(split_ternary(4), (Equal, Greater));
+        assert_eq!(split_ternary(8), (Equal, Less));
+        assert_eq!(split_ternary(9), (Greater, Less));
+        assert_eq!(split_ternary(6), (Less, Greater));
+    }
+
+    // The paired function against the two separate ones, for values and ternary values
+    fn check(f: fn(&Mpfr, mpfr_prec_t, mpfr_rnd_t) -> (Mpfr, Mpfr, Ordering, Ordering),
+             fa: Fn1, fb: Fn1) {
+        for &x in [0.0, 1.0, -0.75, 3.0, 1e-20, 100.0].iter() {
+            for &rnd in [MPFR_RNDN, MPFR_RNDZ, MPFR_RNDU, MPFR_RNDD].iter() {
+                let x = Mpfr::from_f64(x, 53);
+                let (a, b, ta, tb) = f(&x, 24, rnd);
+                let mut a2 = Mpfr::new(24);
+                let mut b2 = Mpfr::new(24);
+                unsafe {
+                    assert_eq!(ta, ordering(fa(a2.as_mut_ptr(), x.as_ptr(), rnd)));
+                    assert_eq!(tb, ordering(fb(b2.as_mut_ptr(), x.as_ptr(), rnd)));
+                    assert!(super::super::mpfr_equal_p(a.as_ptr(), a2.as_ptr()) != 0);
+                    assert!(super::super::mpfr_equal_p(b.as_ptr(), b2.as_ptr()) != 0);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn sin_cos_values() {
+        let (s, c, ts, tc) = sin_cos(&Mpfr::from_f64(0.0, 53), 53, MPFR_RNDN);
+        assert_eq!((s.to_f64(MPFR_RNDN), c.to_f64(MPFR_RNDN), ts, tc), (0.0, 1.0, Equal, Equal));
+        let (_, _, ts, tc) = sin_cos(&Mpfr::from_f64(1.0, 53), 53, MPFR_RNDU);
+        assert_eq!((ts, tc), (Greater, Greater));
+        let (_, _, ts, tc) = sin_cos(&Mpfr::from_f64(1.0, 53), 53, MPFR_RNDD);
+        assert_eq!((ts, tc), (Less, Less));
+        check(sin_cos, super::super::mpfr_sin, super::super::mpfr_cos);
+    }
+
+    #[test]
+    fn sinh_cosh_values() {
+        let (s, c, ts, tc) = sinh_cosh(&Mpfr::from_f64(0.0, 53), 53, MPFR_RNDN);
+        assert_eq!((s.to_f64(MPFR_RNDN), c.to_f64(MPFR_RNDN), ts, tc), (0.0, 1.0, Equal, Equal));
+        check(sinh_cosh, super::super::mpfr_sinh, super::super::mpfr_cosh);
+    }
+
+    #[test]
+    fn modf_values() {
+        let parts = |&: x: f64, prec, rnd| {
+            let (i, f, ti, tf) = modf(&Mpfr::from_f64(x, 53), prec, rnd);
+            (i.to_f64(MPFR_RNDN), f.to_f64(MPFR_RNDN), ti, tf)
+        };
+        assert_eq!(parts(-2.5, 53, MPFR_RNDN), (-2.0, -0.5, Equal, Equal));
+        // 101.0101b with two bits
+        assert_eq!(parts(5.3125, 2, MPFR_RNDU), (6.0, 0.375, Greater, Greater));
+        assert_eq!(parts(5.3125, 2, MPFR_RNDD), (4.0, 0.25, Less, Less));
+        // 100.0101b: the integral part fits, the fractional one does not
+        assert_eq!(parts(4.3125, 2, MPFR_RNDU), (4.0, 0.375, Equal, Greater));
+        // 101.011b: the other way round
+        assert_eq!(parts(5.375, 2, MPFR_RNDD), (4.0, 0.375, Less, Equal));
+    }
+}
@@ -0,0 +1,426 @@
+//! Polynomials with MPFR coefficients: evaluation and real root isolation.
+//!
+//! Roots are isolated with a Sturm sequence computed exactly: MPFR numbers are dyadic
+//! rationals, so pseudo-remainders and evaluations at dyadic points only need exact
+//! products and sums, whose precision grows as needed. Isolating intervals are then refined
+//! by Newton iteration, stopped when `mpfr_nexttoward` shows that the iterate no longer
+//! moves by more than an ulp, with bisection as a fallback, until the root is known to the
+//! requested precision.
+//!
+//! Exact arithmetic makes the Sturm sequence expensive for high degrees or for coefficients
+//! of high precision.
+
+use std::cmp;
+use libc::{c_int, c_ulong};
+
+use super::{mpfr_prec_t, mpfr_exp_t, mpfr_rnd_t, MPFR_RNDN, MPFR_RNDU, MPFR_PREC_MIN};
+use float::Mpfr;
+use inline::{mpfr_get_exp, mpfr_regular_p};
+
+fn round(x: &Mpfr, prec: mpfr_prec_t, rnd: mpfr_rnd_t) -> (Mpfr, c_int) {
+    let mut r = Mpfr::new(prec);
+    let t = unsafe { super::mpfr_set(r.as_mut_ptr(), x.as_ptr(), rnd) };
+    (r, t)
+}
+
+fn equal(x: &Mpfr, y: &Mpfr) -> bool {
+    unsafe { super::mpfr_equal_p(x.as_ptr(), y.as_ptr()) != 0 }
+}
+
+fn less(x: &Mpfr, y: &Mpfr) -> bool {
+    unsafe { super::mpfr_less_p(x.as_ptr(), y.as_ptr()) != 0 }
+}
+
+// Position of the lowest bit of a regular number
+fn low_exp(x: &Mpfr) -> mpfr_exp_t {
+    unsafe { mpfr_get_exp(x.as_ptr()) - x.prec() as mpfr_exp_t }
+}
+
+fn exact_add(x: &Mpfr, y: &Mpfr, negate_y: bool) -> Mpfr {
+    let f = if negate_y { super::mpfr_sub } else { super::mpfr_add };
+    let prec = unsafe {
+        if mpfr_regular_p(x.as_ptr()) == 0 || mpfr_regular_p(y.as_ptr()) == 0 {
+            cmp::max(x.prec(), y.prec())
+        } else {
+            let high = cmp::max(mpfr_get_exp(x.as_ptr()), mpfr_get_exp(y.as_ptr())) + 1;
+            (high - cmp::min(low_exp(x), low_exp(y))) as mpfr_prec_t
+        }
+    };
+    let mut r = Mpfr::new(cmp::max(prec, MPFR_PREC_MIN));
+    unsafe { f(r.as_mut_ptr(), x.as_ptr(), y.as_ptr(), MPFR_RNDN) };
+    r
+}
+
+fn exact_midpoint(a: &Mpfr, b: &Mpfr) -> Mpfr {
+    let mut m = exact_add(a, b, false);
+    unsafe { super::mpfr_div_2ui(m.as_mut_ptr(), m.as_ptr(), 1 as c_ulong, MPFR_RNDN) };
+    m
+}
+
+fn eval_exact(c: &[Mpfr], x: &Mpfr) -> Mpfr {
+    let mut r = Mpfr::zero(MPFR_PREC_MIN);
+    for ci in c.iter().rev() {
+        r = exact_add(&r.exact_mul(x), ci, false);
+    }
+    r
+}
+
+fn strip(c: &mut Vec<Mpfr>) {
+    while c.len() > 0 && c[c.len() - 1].sgn() == 0 {
+        c.pop();
+    }
+}
+
+// A positive multiple of the remainder of a divided by b
+fn pseudo_rem(a: &[Mpfr], b: &[Mpfr]) -> Vec<Mpfr> {
+    let mut r: Vec<Mpfr> = a.iter().map(|c| c.clone()).collect();
+    let lb = &b[b.len() - 1];
+    let mut alb = lb.clone();
+    unsafe { super::mpfr_abs(alb.as_mut_ptr(), lb.as_ptr(), MPFR_RNDN) };
+    strip(&mut r);
+    while r.len() >= b.len() {
+        // r = |lc(b)| r - sign(lc(b)) lc(r) x^k b, which cancels the leading term
+        let k = r.len() - b.len();
+        let mut f = r[r.len() - 1].clone();
+        if lb.sgn() < 0 {
+            unsafe { super::mpfr_neg(f.as_mut_ptr(), f.as_ptr(), MPFR_RNDN) };
+        }
+        for i in 0..r.len() {
+            let mut t = alb.exact_mul(&r[i]);
+            if i >= k {
+                t = exact_add(&t, &f.exact_mul(&b[i - k]), true);
+            }
+            r[i] = t;
+        }
+        r.pop();
+        strip(&mut r);
+    }
+    r
+}
+
+pub struct Poly {
+    // Coefficients by increasing degree, without trailing zeros
+    coeffs: Vec<Mpfr>
+}
+
+impl Poly {
+    pub fn new(coeffs: Vec<Mpfr>) -> Poly {
+        let mut coeffs = coeffs;
+        strip(&mut coeffs);
+        Poly { coeffs: coeffs }
+    }
+
+    pub fn from_f64(coeffs: &[f64]) -> Poly {
+        Poly::new(coeffs.iter().map(|&c| Mpfr::from_f64(c, 53)).collect())
+    }
+
+    #[inline]
+    pub fn coeffs(&self) -> &[Mpfr] { self.coeffs.as_slice() }
+
+    // Degree, or None for the zero polynomial
+    pub fn degree(&self) -> Option<usize> {
+        if self.coeffs.len() == 0 { None } else { Some(self.coeffs.len() - 1) }
+    }
+
+    // Exact derivative
+    pub fn derivative(&self) -> Poly {
+        let coeffs = self.coeffs.iter().enumerate().skip(1).map(|(i, c)| {
+            let mut r = Mpfr::new(c.prec() + 64);
+            unsafe { super::mpfr_mul_ui(r.as_mut_ptr(), c.as_ptr(), i as c_ulong, MPFR_RNDN) };
+            r
+        }).collect();
+        Poly::new(coeffs)
+    }
+
+    // Horner's scheme, rounding once per coefficient with mpfr_fma
+    pub fn eval(&self, x: &Mpfr, prec: mpfr_prec_t, rnd: mpfr_rnd_t) -> Mpfr {
+        let mut r = Mpfr::zero(prec);
+        for c in self.coeffs.iter().rev() {
+            unsafe { super::mpfr_fma(r.as_mut_ptr(), r.as_ptr(), x.as_ptr(), c.as_ptr(), rnd) };
+        }
+        r
+    }
+
+    // Compensated Horner's scheme: the rounding errors of every step are computed exactly
+    // and accumulated in a second Horner evaluation, which is added at the end. The result
+    // is about as accurate as plain evaluation with twice the precision.
+    pub fn eval_compensated(&self, x: &Mpfr, prec: mpfr_prec_t, rnd: mpfr_rnd_t) -> Mpfr {
+        let mut s = Mpfr::zero(prec);
+        let mut e = Mpfr::zero(prec);
+        for c in self.coeffs.iter().rev() {
+            let exact = exact_add(&s.exact_mul(x), c, false);
+            let (next, _) = round(&exact, prec, MPFR_RNDN);
+            let (err, _) = round(&exact_add(&exact, &next, true), prec, MPFR_RNDN);
+            unsafe {
+                super::mpfr_fma(e.as_mut_ptr(), e.as_ptr(), x.as_ptr(), err.as_ptr(), MPFR_RNDN)
+            };
+            s = next;
+        }
+        let mut r = Mpfr::new(prec);
+        unsafe { super::mpfr_add(r.as_mut_ptr(), s.as_ptr(), e.as_ptr(), rnd) };
+        r
+    }
+
+    fn sturm(&self) -> Vec<Vec<Mpfr>> {
+        let mut seq = vec![self.coeffs.clone(), self.derivative().coeffs];
+        loop {
+            let n = seq.len();
+            if seq[n - 1].len() == 0 {
+                seq.pop();
+                break;
+            }
+            let mut r = pseudo_rem(seq[n - 2].as_slice(), seq[n - 1].as_slice());
+            if r.len() == 0 {
+                break;
+            }
+            for c in r.iter_mut() {
+                unsafe { super::mpfr_neg(c.as_mut_ptr(), c.as_ptr(), MPFR_RNDN) };
+            }
+            seq.push(r);
+        }
+        seq
+    }
+
+    // Distinct real roots of the polynomial in increasing order, rounded to nearest with
+    // `prec` bits
+    pub fn real_roots(&self, prec: mpfr_prec_t) -> Vec<Mpfr> {
+        let n = match self.degree() {
+            None | Some(0) => return Vec::new(),
+            Some(n) => n
+        };
+        let seq = self.sturm();
+        // Number of distinct roots in (a, b]
+        let count = |a: &Mpfr, b: &Mpfr| -> usize {
+            let v = |x: &Mpfr| -> usize {
+                let mut changes = 0;
+                let mut last = 0;
+                for p in seq.iter() {
+                    let s = eval_exact(p.as_slice(), x).sgn();
+                    if s != 0 {
+                        if last != 0 && s != last { changes += 1 }
+                        last = s;
+                    }
+                }
+                changes
+            };
+            v(a) - v(b)
+        };
+
+        // All roots have an absolute value below 1 + max |c_i / c_n|, rounded up to a power
+        // of two to keep the bisection points short
+        let mut bound = Mpfr::zero(64);
+        let lead = self.coeffs[n].apply(super::mpfr_abs, self.coeffs[n].prec(), MPFR_RNDN);
+        for c in self.coeffs[..n].iter() {
+            // Quotient of the absolute values, so that rounding up gives an upper bound
+            let mut q = c.apply(super::mpfr_abs, c.prec(), MPFR_RNDN);
+            unsafe {
+                super::mpfr_div(q.as_mut_ptr(), q.as_ptr(), lead.as_ptr(), MPFR_RNDU);
+                super::mpfr_max(bound.as_mut_ptr(), bound.as_ptr(), q.as_ptr(), MPFR_RNDU);
+            }
+        }
+        let mut hi = Mpfr::new(MPFR_PREC_MIN);
+        let mut lo = Mpfr::new(MPFR_PREC_MIN);
+        unsafe {
+            super::mpfr_add_ui(bound.as_mut_ptr(), bound.as_ptr(), 1, MPFR_RNDU);
+            let e = mpfr_get_exp(bound.as_ptr());
+            super::mpfr_set_ui_2exp(hi.as_mut_ptr(), 1, e, MPFR_RNDN);
+            super::mpfr_neg(lo.as_mut_ptr(), hi.as_ptr(), MPFR_RNDN);
+        }
+
+        let mut isolated = Vec::new();
+        let mut pending = vec![(lo, hi)];
+        while let Some((a, b)) = pending.pop() {
+            match count(&a, &b) {
+                0 => {}
+                1 => isolated.push((a, b)),
+                _ => {
+                    let m = exact_midpoint(&a, &b);
+                    pending.push((m.clone(), b));
+                    pending.push((a, m));
+                }
+            }
+        }
+        isolated.sort_by(|x, y| {
+            if less(&x.0, &y.0) { cmp::Ordering::Less } else { cmp::Ordering::Greater }
+        });
+        isolated.into_iter().map(|(a, b)| self.refine(a, b, prec, &count)).collect()
+    }
+
+    // Rounds the only root in (a, b] to nearest with `prec` bits
+    fn refine<F: Fn(&Mpfr, &Mpfr) -> usize>(&self, a: Mpfr, b: Mpfr, prec: mpfr_prec_t,
+                                              count: &F) -> Mpfr {
+        let dp = self.derivative();
+        let (mut a, mut b) = (a, b);
+        let mut wp = prec + 16;
+        loop {
+            if let Some(r) = self.rounded_root(&a, &b, prec) {
+                return r;
+            }
+
+            // Newton iteration from the midpoint while it stays inside the interval
+            let mut x = round(&exact_midpoint(&a, &b), wp, MPFR_RNDN).0;
+            let mut converged = false;
+            for _ in 0..64 {
+                let px = self.eval(&x, wp, MPFR_RNDN);
+                let dx = dp.eval(&x, wp, MPFR_RNDN);
+                if dx.sgn() == 0 {
+                    break;
+                }
+                let mut xn = Mpfr::new(wp);
+                unsafe {
+                    super::mpfr_div(xn.as_mut_ptr(), px.as_ptr(), dx.as_ptr(), MPFR_RNDN);
+                    super::mpfr_sub(xn.as_mut_ptr(), x.as_ptr(), xn.as_ptr(), MPFR_RNDN);
+                }
+                if !less(&a, &xn) || less(&b, &xn) {
+                    break;
+                }
+                // Converged once the step is at most one ulp
+                let mut next = x.clone();
+                unsafe { super::mpfr_nexttoward(next.as_mut_ptr(), xn.as_ptr()) };
+                let done = equal(&xn, &x) || equal(&xn, &next);
+                x = xn;
+                if done {
+                    converged = true;
+                    break;
+                }
+            }
+
+            let mut shrunk = false;
+            if converged {
+                // Bracket the iterate by a few ulps and check the root is still inside
+                let mut l = x.clone();
+                let mut h = x.clone();
+                for _ in 0..4 {
+                    unsafe {
+                        super::mpfr_nextbelow(l.as_mut_ptr());
+                        super::mpfr_nextabove(h.as_mut_ptr());
+                    }
+                }
+                if less(&a, &l) && less(&h, &b) && count(&l, &h) == 1 {
+                    a = l;
+                    b = h;
+                    wp *= 2;
+                    shrunk = true;
+                }
+            }
+            if !shrunk {
+                let m = exact_midpoint(&a, &b);
+                if count(&a, &m) == 1 { b = m } else { a = m }
+            }
+        }
+    }
+
+    // The root in (a, b] rounded to nearest if all of (a, b] rounds to the same number
+    fn rounded_root(&self, a: &Mpfr, b: &Mpfr, prec: mpfr_prec_t) -> Option<Mpfr> {
+        if eval_exact(self.coeffs.as_slice(), b).sgn() == 0 {
+            return Some(round(b, prec, MPFR_RNDN).0);
+        }
+        // Numbers just above a midpoint between two representable numbers round up
+        let (_, t) = round(a, prec + 1, MPFR_RNDN);
+        let (_, t2) = round(a, prec, MPFR_RNDN);
+        let rnd_a = if t == 0 && t2 != 0 { MPFR_RNDU } else { MPFR_RNDN };
+        let (ra, _) = round(a, prec, rnd_a);
+        let (rb, _) = round(b, prec, MPFR_RNDN);
+        if equal(&ra, &rb) { Some(rb) } else { None }
+    }
+}
+
+impl Clone for Poly {
+    fn clone(&self) -> Poly {
+        Poly { coeffs: self.coeffs.clone() }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::num::Float;
+
+    use super::Poly;
+    use super::super::{mpfr_prec_t, MPFR_RNDN};
+    use float::Mpfr;
+
+    fn roots(p: &Poly, prec: mpfr_prec_t) -> Vec<f64> {
+        p.real_roots(prec).iter().map(|r| r.to_f64(MPFR_RNDN)).collect()
+    }
+
+    // Coefficients of the product of (x - r) over the given roots
+    fn from_roots(rs: &[f64]) -> Poly {
+        let mut c = vec![Mpfr::from_ui(1, 53)];
+        for &r in rs.iter() {
+            let r = Mpfr::from_f64(r, 53);
+            let mut next = vec![Mpfr::zero(53)];
+            next.extend(c.iter().map(|x| x.clone()));
+            for (i, x) in c.iter().enumerate() {
+                let t = x.exact_mul(&r);
+                next[i] = next[i].apply2(super::super::mpfr_sub, &t, 400, MPFR_RNDN);
+            }
+            c = next;
+        }
+        Poly::new(c)
+    }
+
+    #[test]
+    fn derivative() {
+        let p = Poly::from_f64(&[5.0, -2.0, 0.0, 1.0]).derivative();
+        let c: Vec<f64> = p.coeffs().iter().map(|c| c.to_f64(MPFR_RNDN)).collect();
+        assert_eq!(c, vec![-2.0, 0.0, 3.0]);
+        assert_eq!(Poly::from_f64(&[7.0]).derivative().degree(), None);
+    }
+
+    #[test]
+    fn compensated_evaluation() {
+        // (x - 1)^7 at 1 + 2^-5 is 2^-35, while the terms are up to 35 in magnitude
+        let p = from_roots(&[1.0; 7]);
+        let x = Mpfr::from_f64(1.03125, 53);
+        let exact = p.eval(&x, 400, MPFR_RNDN);
+        assert_eq!(exact.to_f64(MPFR_RNDN), 2.0f64.powi(-35));
+        let r = p.eval_compensated(&x, 53, MPFR_RNDN).to_f64(MPFR_RNDN);
+        assert!((r - 2.0f64.powi(-35)).abs() <= 2.0f64.powi(-35 - 50), "{}", r);
+    }
+
+    #[test]
+    fn simple_roots() {
+        assert_eq!(roots(&from_roots(&[3.0, 1.0, 2.0]), 53), vec![1.0, 2.0, 3.0]);
+        assert_eq!(roots(&Poly::from_f64(&[-2.0, 0.0, 1.0]), 53),
+                   vec![-2.0f64.sqrt(), 2.0f64.sqrt()]);
+        assert!(roots(&Poly::from_f64(&[1.0, 0.0, 1.0]), 53).is_empty());
+        assert!(roots(&Poly::from_f64(&[3.0]), 53).is_empty());
+
+        // Rounded to nearest at higher precision too
+        let mut sqrt2 = Mpfr::new(200);
+        unsafe { super::super::mpfr_sqrt_ui(sqrt2.as_mut_ptr(), 2, MPFR_RNDN) };
+        let r = Poly::from_f64(&[-2.0, 0.0, 1.0]).real_roots(200);
+        assert!(unsafe { super::super::mpfr_equal_p(r[1].as_ptr(), sqrt2.as_ptr()) != 0 });
+    }
+
+    #[test]
+    fn multiple_roots() {
+        // (x - 1)^2 (x + 2) and (x - 0.5)^3 (x + 1)^2
+        assert_eq!(roots(&from_roots(&[1.0, 1.0, -2.0]), 53), vec![-2.0, 1.0]);
+        assert_eq!(roots(&from_roots(&[0.5, 0.5, 0.5, -1.0, -1.0]), 53), vec![-1.0, 0.5]);
+    }
+
+    #[test]
+    fn roots_at_zero() {
+        assert_eq!(roots(&Poly::from_f64(&[0.0, -1.0, 0.0, 1.0]), 53), vec![-1.0, 0.0, 1.0]);
+        assert_eq!(roots(&Poly::from_f64(&[0.0, 0.0, -3.0, 1.0]), 53), vec![0.0, 3.0]);
+    }
+
+    #[test]
+    fn close_roots() {
+        let e = 2.0f64.powi(-30);
+        assert_eq!(roots(&from_roots(&[1.0, 1.0 + e]), 53), vec![1.0, 1.0 + e]);
+        assert_eq!(roots(&from_roots(&[-e, e, 2.0 * e]), 53), vec![-e, e, 2.0 * e]);
+    }
+
+    #[test]
+    fn large_coefficients() {
+        // Roots far from 1, where the bound on their magnitude comes from the quotients of
+        // coefficients of opposite signs
+        assert_eq!(roots(&from_roots(&[-3.0, 1e6]), 53), vec![-3.0, 1e6]);
+        assert_eq!(roots(&Poly::from_f64(&[-1.0, 0.0, 2.0f64.powi(-40)]), 53),
+                   vec![-2.0f64.powi(20), 2.0f64.powi(20)]);
+        assert_eq!(roots(&Poly::from_f64(&[1e300, -1.0]), 53), vec![1e300]);
+        assert_eq!(roots(&Poly::from_f64(&[-1e-300, 1e-300, 1e-300]), 53).len(), 2);
+    }
+}
@@ -0,0 +1,517 @@
+//! Minimax polynomial and rational approximations with the Remez exchange algorithm.
+//!
+//! The target function is evaluated with MPFR at the working precision, so the computed
+//! coefficients are not limited by double precision. Every iteration solves for an
+//! approximation whose weighted error alternates in sign with equal magnitude on the
+//! reference points, then replaces the reference by the extrema of the error, located on
+//! a dense grid and refined by golden-section search. Rational approximations linearize the
+//! system around the previous levelled error.
+//!
+//! `Remez::max_error` bounds the error of any approximation, e.g. one with coefficients
+//! rounded to double, with interval arithmetic over a subdivision of the interval, given an
+//! enclosure of the target function; `Remez::estimated_error` only evaluates the error at
+//! the extrema it locates, which is cheaper but not a proof of the bound.
+
+use std::cmp;
+use libc::{c_long, c_ulong};
+
+use super::{mpfr_prec_t, MPFR_RNDN};
+use float::Mpfr;
+use interval::Interval;
+
+// Grid points per reference point for locating extrema
+const GRID: usize = 32;
+// Golden-section steps refining each extremum
+const REFINE_STEPS: usize = 60;
+// Iteration stops when the extrema agree to this many bits
+const LEVEL_BITS: c_long = 20;
+const MAX_ITERATIONS: usize = 50;
+
+fn add(x: &Mpfr, y: &Mpfr, prec: mpfr_prec_t) -> Mpfr {
+    x.apply2(super::mpfr_add, y, prec, MPFR_RNDN)
+}
+
+fn sub(x: &Mpfr, y: &Mpfr, prec: mpfr_prec_t) -> Mpfr {
+    x.apply2(super::mpfr_sub, y, prec, MPFR_RNDN)
+}
+
+fn mul(x: &Mpfr, y: &Mpfr, prec: mpfr_prec_t) -> Mpfr {
+    x.apply2(super::mpfr_mul, y, prec, MPFR_RNDN)
+}
+
+fn div(x: &Mpfr, y: &Mpfr, prec: mpfr_prec_t) -> Mpfr {
+    x.apply2(super::mpfr_div, y, prec, MPFR_RNDN)
+}
+
+fn abs(x: &Mpfr, prec: mpfr_prec_t) -> Mpfr {
+    x.apply(super::mpfr_abs, prec, MPFR_RNDN)
+}
+
+// |x| < |y|
+fn abs_less(x: &Mpfr, y: &Mpfr) -> bool {
+    unsafe { super::mpfr_cmpabs(x.as_ptr(), y.as_ptr()) < 0 }
+}
+
+// a + (b - a) * i / n
+fn lerp(a: &Mpfr, b: &Mpfr, i: usize, n: usize, prec: mpfr_prec_t) -> Mpfr {
+    let t = div(&Mpfr::from_ui(i as c_ulong, prec), &Mpfr::from_ui(n as c_ulong, prec), prec);
+    add(a, &mul(&sub(b, a, prec), &t, prec), prec)
+}
+
+fn horner(c: &[Mpfr], x: &Mpfr, prec: mpfr_prec_t) -> Mpfr {
+    let mut r = Mpfr::zero(prec);
+    for ci in c.iter().rev() {
+        unsafe { super::mpfr_fma(r.as_mut_ptr(), r.as_ptr(), x.as_ptr(), ci.as_ptr(), MPFR_RNDN) };
+    }
+    r
+}
+
+fn horner_interval(c: &[Mpfr], x: &Interval) -> Interval {
+    let prec = x.prec();
+    let mut r = Interval::point(&Mpfr::zero(prec), prec);
+    for ci in c.iter().rev() {
+        r = r.mul(x).add(&Interval::point(ci, prec));
+    }
+    r
+}
+
+// Gaussian elimination with partial pivoting; None for a singular system
+fn solve(a: Vec<Vec<Mpfr>>, b: Vec<Mpfr>, prec: mpfr_prec_t) -> Option<Vec<Mpfr>> {
+    let (mut a, mut b) = (a, b);
+    let n = b.len();
+    for col in 0..n {
+        let mut pivot = col;
+        for r in col + 1..n {
+            if abs_less(&a[pivot][col], &a[r][col]) {
+                pivot = r;
+            }
+        }
+        if a[pivot][col].sgn() == 0 {
+            return None;
+        }
+        a.swap(col, pivot);
+        b.swap(col, pivot);
+        for r in col + 1..n {
+            let factor = div(&a[r][col], &a[col][col], prec);
+            for c in col..n {
+                let t = sub(&a[r][c], &mul(&factor, &a[col][c], prec), prec);
+                a[r][c] = t;
+            }
+            let t = sub(&b[r], &mul(&factor, &b[col], prec), prec);
+            b[r] = t;
+        }
+    }
+    let mut x: Vec<Mpfr> = (0..n).map(|_| Mpfr::zero(prec)).collect();
+    for i in (0..n).rev() {
+        let mut s = b[i].clone();
+        for j in i + 1..n {
+            s = sub(&s, &mul(&a[i][j], &x[j], prec), prec);
+        }
+        x[i] = div(&s, &a[i][i], prec);
+    }
+    Some(x)
+}
+
+#[derive(Copy, PartialEq)]
+pub enum Weight {
+    // Minimize max |f - r|
+    Absolute,
+    // Minimize max |(f - r) / f|; f must not vanish on the interval
+    Relative
+}
+
+// p(x) / q(x), coefficients by increasing degree, with q(0) = 1
+pub struct Approximation {
+    pub num: Vec<Mpfr>,
+    pub den: Vec<Mpfr>
+}
+
+impl Approximation {
+    pub fn eval(&self, x: &Mpfr, prec: mpfr_prec_t) -> Mpfr {
+        let p = horner(self.num.as_slice(), x, prec);
+        if self.den.len() == 1 { p } else { div(&p, &horner(self.den.as_slice(), x, prec), prec) }
+    }
+
+    // Enclosure of p(x) / q(x) for all points of x
+    pub fn eval_interval(&self, x: &Interval) -> Interval {
+        let p = horner_interval(self.num.as_slice(), x);
+        if self.den.len() == 1 { p } else { p.div(&horner_interval(self.den.as_slice(), x)) }
+    }
+
+    // The same approximation with coefficients rounded to nearest with `prec` bits, e.g. 53
+    // or 24 for coefficients stored as f64 or f32
+    pub fn round(&self, prec: mpfr_prec_t) -> Approximation {
+        let r = |c: &[Mpfr]| -> Vec<Mpfr> {
+            c.iter().map(|x| {
+                let mut y = Mpfr::new(prec);
+                unsafe { super::mpfr_set(y.as_mut_ptr(), x.as_ptr(), MPFR_RNDN) };
+                y
+            }).collect()
+        };
+        Approximation { num: r(self.num.as_slice()), den: r(self.den.as_slice()) }
+    }
+
+    pub fn num_f64(&self) -> Vec<f64> {
+        self.num.iter().map(|c| c.to_f64(MPFR_RNDN)).collect()
+    }
+
+    pub fn den_f64(&self) -> Vec<f64> {
+        self.den.iter().map(|c| c.to_f64(MPFR_RNDN)).collect()
+    }
+
+    pub fn num_f32(&self) -> Vec<f32> {
+        self.num.iter().map(|c| unsafe { super::mpfr_get_flt(c.as_ptr(), MPFR_RNDN) as f32 })
+            .collect()
+    }
+
+    pub fn den_f32(&self) -> Vec<f32> {
+        self.den.iter().map(|c| unsafe { super::mpfr_get_flt(c.as_ptr(), MPFR_RNDN) as f32 })
+            .collect()
+    }
+}
+
+// Outcome of the exchange algorithm
+pub enum Fit {
+    // The extrema of the error agree to LEVEL_BITS bits, or the error vanishes
+    Converged(Approximation),
+    // The last approximation found when the iteration stopped without levelling the error,
+    // after MAX_ITERATIONS or on a singular system; its error is not the minimax one
+    Unconverged(Approximation)
+}
+
+impl Fit {
+    pub fn is_converged(&self) -> bool {
+        match *self { Fit::Converged(_) => true, Fit::Unconverged(_) => false }
+    }
+
+    pub fn approximation(self) -> Approximation {
+        match self { Fit::Converged(r) | Fit::Unconverged(r) => r }
+    }
+}
+
+// Approximation problem for f on [a, b]; f(x, prec) must return f(x) with about `prec`
+// correct bits, e.g. by calling mpfr_exp with a result of that precision
+pub struct Remez<F> {
+    f: F,
+    a: Mpfr,
+    b: Mpfr,
+    weight: Weight,
+    prec: mpfr_prec_t
+}
+
+impl<F: Fn(&Mpfr, mpfr_prec_t) -> Mpfr> Remez<F> {
+    pub fn new(f: F, a: f64, b: f64, weight: Weight, prec: mpfr_prec_t) -> Remez<F> {
+        assert!(a < b, "empty interval [{}, {}]", a, b);
+        Remez { f: f, a: Mpfr::from_f64(a, 53), b: Mpfr::from_f64(b, 53), weight: weight,
+                prec: prec }
+    }
+
+    // Weighted error at x
+    fn error(&self, r: &Approximation, x: &Mpfr) -> Mpfr {
+        let prec = self.prec;
+        let y = (self.f)(x, prec);
+        let d = sub(&y, &r.eval(x, prec), prec);
+        match self.weight {
+            Weight::Absolute => d,
+            Weight::Relative => div(&d, &abs(&y, prec), prec)
+        }
+    }
+
+    // Local extrema of the error in increasing order, as (x, error at x)
+    fn extrema(&self, r: &Approximation, points: usize) -> Vec<(Mpfr, Mpfr)> {
+        let prec = self.prec;
+        let n = GRID * points;
+        let xs: Vec<Mpfr> = (0..n + 1).map(|i| lerp(&self.a, &self.b, i, n, prec)).collect();
+        let es: Vec<Mpfr> = xs.iter().map(|x| self.error(r, x)).collect();
+        let mut result = Vec::new();
+        for i in 0..n + 1 {
+            let left = i == 0 || !abs_less(&es[i], &es[i - 1]);
+            let right = i == n || !abs_less(&es[i], &es[i + 1]);
+            if !(left && right) {
+                continue;
+            }
+            if i == 0 || i == n {
+                result.push((xs[i].clone(), es[i].clone()));
+            } else {
+                result.push(self.refine(r, &xs[i - 1], &xs[i + 1]));
+            }
+        }
+        result
+    }
+
+    // Golden-section search for the maximum of |error| on [lo, hi]
+    fn refine(&self, r: &Approximation, lo: &Mpfr, hi: &Mpfr) -> (Mpfr, Mpfr) {
+        let prec = self.prec;
+        let mut inv_phi = Mpfr::new(prec);
+        unsafe {
+            // (sqrt(5) - 1) / 2
+            super::mpfr_sqrt_ui(inv_phi.as_mut_ptr(), 5, MPFR_RNDN);
+            super::mpfr_sub_ui(inv_phi.as_mut_ptr(), inv_phi.as_ptr(), 1, MPFR_RNDN);
+            super::mpfr_div_2ui(inv_phi.as_mut_ptr(), inv_phi.as_ptr(), 1, MPFR_RNDN);
+        }
+        let (mut lo, mut hi) = (lo.clone(), hi.clone());
+        let step = |lo: &Mpfr, hi: &Mpfr| mul(&sub(hi, lo, prec), &inv_phi, prec);
+        let mut x1 = sub(&hi, &step(&lo, &hi), prec);
+        let mut x2 = add(&lo, &step(&lo, &hi), prec);
+        let mut e1 = self.error(r, &x1);
+        let mut e2 = self.error(r, &x2);
+        for _ in 0..REFINE_STEPS {
+            if abs_less(&e1, &e2) {
+                lo = x1;
+                x1 = x2;
+                e1 = e2;
+                x2 = add(&lo, &step(&lo, &hi), prec);
+                e2 = self.error(r, &x2);
+            } else {
+                hi = x2;
+                x2 = x1;
+                e2 = e1;
+                x1 = sub(&hi, &step(&lo, &hi), prec);
+                e1 = self.error(r, &x1);
+            }
+        }
+        if abs_less(&e1, &e2) { (x2, e2) } else { (x1, e1) }
+    }
+
+    // Solves for the approximation levelling the error on the reference
+    fn level(&self, reference: &[Mpfr], n: usize, m: usize) -> Option<(Approximation, Mpfr)> {
+        let prec = self.prec;
+        let ys: Vec<Mpfr> = reference.iter().map(|x| (self.f)(x, prec)).collect();
+        let mut e_prev = Mpfr::zero(prec);
+        let mut result = None;
+        // The rational system is linearized around the previous levelled error
+        for _ in 0..(if m == 0 { 1 } else { 16 }) {
+            let mut rows = Vec::new();
+            for (i, (x, y)) in reference.iter().zip(ys.iter()).enumerate() {
+                // s_i / w_i
+                let mut s = match self.weight {
+                    Weight::Absolute => Mpfr::from_ui(1, prec),
+                    Weight::Relative => abs(y, prec)
+                };
+                if i % 2 == 1 {
+                    unsafe { super::mpfr_neg(s.as_mut_ptr(), s.as_ptr(), MPFR_RNDN) };
+                }
+                let mut row = Vec::with_capacity(n + m + 2);
+                let mut xk = Mpfr::from_ui(1, prec);
+                for _ in 0..n + 1 {
+                    row.push(xk.clone());
+                    xk = mul(&xk, x, prec);
+                }
+                let qf = sub(&mul(&s, &e_prev, prec), y, prec);
+                let mut xj = x.clone();
+                for _ in 0..m {
+                    row.push(mul(&qf, &xj, prec));
+                    xj = mul(&xj, x, prec);
+                }
+                row.push(s);
+                rows.push(row);
+            }
+            let sol = match solve(rows, ys.clone(), prec) {
+                Some(sol) => sol,
+                None => return result
+            };
+            let e = sol[n + m + 1].clone();
+            let mut den = vec![Mpfr::from_ui(1, prec)];
+            den.extend(sol[n + 1..n + m + 1].iter().map(|c| c.clone()));
+            let approx = Approximation { num: sol[..n + 1].to_vec(), den: den };
+            let converged = m == 0 || {
+                let mut tol = abs(&e, prec);
+                unsafe { super::mpfr_mul_2si(tol.as_mut_ptr(), tol.as_ptr(), -40, MPFR_RNDN) };
+                !abs_less(&tol, &sub(&e, &e_prev, prec))
+            };
+            e_prev = e.clone();
+            result = Some((approx, e));
+            if converged {
+                break;
+            }
+        }
+        result
+    }
+
+    // Minimax approximation p / q with deg p = n and deg q = m, or None if the first linear
+    // system is singular (e.g. for a rational degree which does not fit the function)
+    pub fn rational(&self, n: usize, m: usize) -> Option<Fit> {
+        let prec = self.prec;
+        let points = n + m + 2;
+
+        // Chebyshev nodes in increasing order
+        let mut pi = Mpfr::new(prec);
+        unsafe { super::mpfr_const_pi(pi.as_mut_ptr(), MPFR_RNDN) };
+        let mid = div(&add(&self.a, &self.b, prec), &Mpfr::from_ui(2, prec), prec);
+        let half = div(&sub(&self.b, &self.a, prec), &Mpfr::from_ui(2, prec), prec);
+        let mut reference: Vec<Mpfr> = (0..points).rev().map(|i| {
+            let i = Mpfr::from_ui(i as c_ulong, prec);
+            let last = Mpfr::from_ui((points - 1) as c_ulong, prec);
+            let mut c = div(&mul(&pi, &i, prec), &last, prec);
+            unsafe { super::mpfr_cos(c.as_mut_ptr(), c.as_ptr(), MPFR_RNDN) };
+            add(&mid, &mul(&half, &c, prec), prec)
+        }).collect();
+
+        let mut best = None;
+        for _ in 0..MAX_ITERATIONS {
+            let (approx, _) = match self.level(reference.as_slice(), n, m) {
+                Some(r) => r,
+                None => return best.map(Fit::Unconverged)
+            };
+
+            // Keep the largest extremum of each run of the same sign, then drop the
+            // smaller ends until the reference has the right size
+            let mut alt: Vec<(Mpfr, Mpfr)> = Vec::new();
+            for (x, e) in self.extrema(&approx, points).into_iter() {
+                if e.sgn() == 0 {
+                    continue;
+                }
+                let same = alt.last().map_or(false, |last| last.1.sgn() == e.sgn());
+                if same {
+                    let replace = abs_less(&alt[alt.len() - 1].1, &e);
+                    if replace {
+                        alt.pop();
+                        alt.push((x, e));
+                    }
+                } else {
+                    alt.push((x, e));
+                }
+            }
+            while alt.len() > points {
+                if abs_less(&alt[0].1, &alt[alt.len() - 1].1) { alt.remove(0); } else { alt.pop(); }
+            }
+            if alt.is_empty() {
+                return Some(Fit::Converged(approx));
+            }
+            if alt.len() < points {
+                return Some(Fit::Unconverged(approx));
+            }
+
+            let mut lo = abs(&alt[0].1, prec);
+            let mut hi = lo.clone();
+            for &(_, ref e) in alt.iter() {
+                if abs_less(e, &lo) { lo = abs(e, prec) }
+                if abs_less(&hi, e) { hi = abs(e, prec) }
+            }
+            reference = alt.into_iter().map(|(x, _)| x).collect();
+
+            let mut tol = hi.clone();
+            unsafe { super::mpfr_mul_2si(tol.as_mut_ptr(), tol.as_ptr(), -LEVEL_BITS, MPFR_RNDN) };
+            if !abs_less(&tol, &sub(&hi, &lo, prec)) {
+                return Some(Fit::Converged(approx));
+            }
+            best = Some(approx);
+        }
+        best.map(Fit::Unconverged)
+    }
+
+    pub fn polynomial(&self, n: usize) -> Option<Fit> {
+        self.rational(n, 0)
+    }
+
+    // Upper bound on the absolute value of the weighted error of r over [a, b], from
+    // interval evaluations on `pieces` subintervals covering it; `f` must return an interval
+    // containing f(x) for every point x of its argument, e.g. `|x| x.exp()`. The bound
+    // exceeds the true maximum by about the width of the enclosures, roughly
+    // (|f'| + |r'|) (b - a) / pieces; it is infinite if the enclosure of the
+    // denominator, or of f for a relative error, contains zero on some piece.
+    pub fn max_error<G>(&self, r: &Approximation, f: G, pieces: usize) -> Mpfr
+        where G: Fn(&Interval) -> Interval
+    {
+        assert!(pieces > 0, "no subintervals");
+        let prec = self.prec;
+        // The ends are exactly a and b, and each piece starts where the previous one ends
+        let bound = |i: usize| -> Mpfr {
+            if i == pieces { self.b.clone() } else { lerp(&self.a, &self.b, i, pieces, prec) }
+        };
+        let mut max = Mpfr::zero(prec);
+        let mut lo = bound(0);
+        for i in 1..pieces + 1 {
+            let hi = bound(i);
+            let x = Interval::new(&lo, &hi, prec);
+            let y = f(&x);
+            let d = y.sub(&r.eval_interval(&x));
+            // Dividing by y rather than |y| leaves the magnitude unchanged
+            let e = match self.weight {
+                Weight::Absolute => d,
+                Weight::Relative => d.div(&y)
+            };
+            if e.is_empty() {
+                let mut inf = Mpfr::new(prec);
+                unsafe { super::mpfr_set_inf(inf.as_mut_ptr(), 1) };
+                return inf;
+            }
+            for end in [e.lo(), e.hi()].iter() {
+                if abs_less(&max, *end) {
+                    max = abs(*end, prec);
+                }
+            }
+            lo = hi;
+        }
+        max
+    }
+
+    // Maximum absolute value of the weighted error of r over [a, b], measured at its
+    // extrema located at the working precision; a lower bound in practice, but unproven
+    pub fn estimated_error(&self, r: &Approximation) -> Mpfr {
+        let points = cmp::max(r.num.len() + r.den.len(), 2);
+        let mut max = Mpfr::zero(self.prec);
+        for (_, e) in self.extrema(r, points).into_iter() {
+            if abs_less(&max, &e) {
+                max = abs(&e, self.prec);
+            }
+        }
+        max
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::num::Float;
+
+    use super::{Remez, Weight};
+    use super::super::{mpfr_prec_t, MPFR_RNDN};
+    use float::Mpfr;
+    use interval::Interval;
+
+    fn exp(x: &Mpfr, prec: mpfr_prec_t) -> Mpfr {
+        x.apply(super::super::mpfr_exp, prec, MPFR_RNDN)
+    }
+
+    fn less(x: &Mpfr, y: &Mpfr) -> bool {
+        unsafe { super::super::mpfr_less_p(x.as_ptr(), y.as_ptr()) != 0 }
+    }
+
+    #[test]
+    fn polynomial_error_is_bounded() {
+        let remez = Remez::new(exp, 0.0, 1.0, Weight::Absolute, 128);
+        let fit = remez.polynomial(3).unwrap();
+        assert!(fit.is_converged());
+        let r = fit.approximation();
+
+        // Degree 3 minimax error of exp on [0, 1], about 5.5e-4
+        let estimate = remez.estimated_error(&r);
+        let e = estimate.to_f64(MPFR_RNDN);
+        assert!(5.0e-4 < e && e < 6.0e-4, "estimated error {}", e);
+
+        // The enclosures add about (|exp'| + |r'|) * 2^-16 to the error
+        let bound = remez.max_error(&r, |x: &Interval| x.exp(), 1 << 16);
+        assert!(!less(&bound, &estimate));
+        assert!(bound.to_f64(MPFR_RNDN) < 1.5 * e, "bound {}", bound.to_f64(MPFR_RNDN));
+    }
+
+    #[test]
+    fn rounded_coefficients_are_bounded() {
+        let remez = Remez::new(exp, -0.5, 0.5, Weight::Relative, 128);
+        let r = remez.rational(2, 2).unwrap().approximation().round(24);
+        let estimate = remez.estimated_error(&r);
+        let bound = remez.max_error(&r, |x: &Interval| x.exp(), 1 << 12);
+        assert!(!less(&bound, &estimate));
+        assert!(bound.to_f64(MPFR_RNDN).is_finite());
+    }
+
+    #[test]
+    fn enclosure_containing_zero_gives_no_bound() {
+        let remez = Remez::new(exp, -1.0, 1.0, Weight::Absolute, 64);
+        let r = remez.polynomial(2).unwrap().approximation();
+        let rel = Remez::new(exp, -1.0, 1.0, Weight::Relative, 64);
+        // An enclosure of x, which vanishes at 0, in place of exp
+        let bound = rel.max_error(&r, |x: &Interval| x.clone(), 8);
+        assert!(bound.to_f64(MPFR_RNDN).is_infinite());
+    }
+}
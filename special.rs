@@ -0,0 +1,199 @@
+//! Safe wrappers for the special functions.
+//!
+//! Every function computes its result with the requested precision and rounding mode and
+//! returns it together with the direction of rounding. MPFR returns NaN outside of the
+//! domain of a function (and for NaN arguments); such results are reported as
+//! `DomainError` instead.
+
+use std::cmp::Ordering;
+use libc::{c_int, c_long, c_ulong};
+
+use super::{mpfr_prec_t, mpfr_rnd_t};
+use float::{Mpfr, ordering};
+use inline::{mpfr_sgn, mpfr_inf_p, mpfr_zero_p};
+
+// The function which returned NaN
+#[derive(Copy, PartialEq)]
+pub struct DomainError {
+    pub function: &'static str
+}
+
+// Sign of Gamma(x) reported by lgamma
+#[derive(Copy, PartialEq)]
+pub enum Sign {
+    Positive,
+    Negative
+}
+
+fn check(function: &'static str, r: Mpfr, ternary: c_int) -> Result<(Mpfr, Ordering), DomainError> {
+    if r.is_nan() {
+        Err(DomainError { function: function })
+    } else {
+        Ok((r, ordering(ternary)))
+    }
+}
+
+macro_rules! unary {
+    ($($name:ident => $f:ident),+) => { $(
+        pub fn $name(x: &Mpfr, prec: mpfr_prec_t, rnd: mpfr_rnd_t)
+                     -> Result<(Mpfr, Ordering), DomainError> {
+            let mut r = Mpfr::new(prec);
+            let t = unsafe { super::$f(r.as_mut_ptr(), x.as_ptr(), rnd) };
+            check(stringify!($f), r, t)
+        }
+    )+ }
+}
+
+macro_rules! order_n {
+    ($($name:ident => $f:ident),+) => { $(
+        pub fn $name(n: c_long, x: &Mpfr, prec: mpfr_prec_t, rnd: mpfr_rnd_t)
+                     -> Result<(Mpfr, Ordering), DomainError> {
+            let mut r = Mpfr::new(prec);
+            let t = unsafe { super::$f(r.as_mut_ptr(), n, x.as_ptr(), rnd) };
+            check(stringify!($f), r, t)
+        }
+    )+ }
+}
+
+// Gamma(x); fails for negative integers and -Inf, gives +/-Inf for +/-0
+unary!(gamma => mpfr_gamma);
+
+// log(Gamma(x)); fails for -2k-1 < x < -2k, where Gamma(x) is negative, and gives +Inf for
+// infinities and nonpositive integers, where |Gamma(x)| is infinite. MPFR 3.1 returns NaN
+// for -0, -Inf and negative integers too, so these are handled here as newer MPFR does
+pub fn lngamma(x: &Mpfr, prec: mpfr_prec_t, rnd: mpfr_rnd_t)
+               -> Result<(Mpfr, Ordering), DomainError> {
+    let mut r = Mpfr::new(prec);
+    let pole = unsafe {
+        let x = x.as_ptr();
+        mpfr_zero_p(x) != 0 || mpfr_inf_p(x) != 0 ||
+            (mpfr_sgn(x) < 0 && super::mpfr_integer_p(x) != 0)
+    };
+    if pole {
+        unsafe { super::mpfr_set_inf(r.as_mut_ptr(), 1) };
+        return Ok((r, Ordering::Equal));
+    }
+    let t = unsafe { super::mpfr_lngamma(r.as_mut_ptr(), x.as_ptr(), rnd) };
+    check("mpfr_lngamma", r, t)
+}
+
+// Digamma (Psi) function; fails for negative integers and -Inf
+unary!(digamma => mpfr_digamma);
+
+// Riemann zeta function; gives +Inf for 1
+unary!(zeta => mpfr_zeta);
+
+// Error and complementary error functions, defined on all reals
+unary!(erf => mpfr_erf, erfc => mpfr_erfc);
+
+// Exponential integral; fails for negative x
+unary!(eint => mpfr_eint);
+
+// Real part of the dilogarithm, defined on all reals
+unary!(li2 => mpfr_li2);
+
+// Airy function Ai, defined on all reals
+unary!(ai => mpfr_ai);
+
+// Bessel functions of the first kind, defined on all reals
+unary!(j0 => mpfr_j0, j1 => mpfr_j1);
+order_n!(jn => mpfr_jn);
+
+// Bessel functions of the second kind; fail for negative x, give -Inf for 0
+unary!(y0 => mpfr_y0, y1 => mpfr_y1);
+order_n!(yn => mpfr_yn);
+
+// zeta(n) for a non-negative integer n; gives +Inf for 1
+pub fn zeta_ui(n: c_ulong, prec: mpfr_prec_t, rnd: mpfr_rnd_t)
+               -> Result<(Mpfr, Ordering), DomainError> {
+    let mut r = Mpfr::new(prec);
+    let t = unsafe { super::mpfr_zeta_ui(r.as_mut_ptr(), n, rnd) };
+    check("mpfr_zeta_ui", r, t)
+}
+
+// log|Gamma(x)| together with the sign of Gamma(x); fails for negative integers and -Inf,
+// where the sign is undefined and MPFR returns +Inf
+pub fn lgamma(x: &Mpfr, prec: mpfr_prec_t, rnd: mpfr_rnd_t)
+              -> Result<(Mpfr, Ordering, Sign), DomainError> {
+    let pole = unsafe {
+        let x = x.as_ptr();
+        mpfr_sgn(x) < 0 && (mpfr_inf_p(x) != 0 || super::mpfr_integer_p(x) != 0)
+    };
+    if pole {
+        return Err(DomainError { function: "mpfr_lgamma" });
+    }
+    let mut r = Mpfr::new(prec);
+    let mut sign: c_int = 0;
+    let t = unsafe { super::mpfr_lgamma(r.as_mut_ptr(), &mut sign, x.as_ptr(), rnd) };
+    let sign = if sign < 0 { Sign::Negative } else { Sign::Positive };
+    check("mpfr_lgamma", r, t).map(|(r, o)| (r, o, sign))
+}
+
+// Arithmetic-geometric mean; fails if either argument is negative
+pub fn agm(x: &Mpfr, y: &Mpfr, prec: mpfr_prec_t, rnd: mpfr_rnd_t)
+           -> Result<(Mpfr, Ordering), DomainError> {
+    let mut r = Mpfr::new(prec);
+    let t = unsafe { super::mpfr_agm(r.as_mut_ptr(), x.as_ptr(), y.as_ptr(), rnd) };
+    check("mpfr_agm", r, t)
+}
+
+#[cfg(test)]
+mod test {
+    use std::cmp::Ordering;
+    use std::num::Float;
+
+    use super::{gamma, lngamma, lgamma, Sign};
+    use super::super::MPFR_RNDN;
+    use float::Mpfr;
+
+    fn value(r: Result<(Mpfr, Ordering), super::DomainError>) -> f64 {
+        r.ok().unwrap().0.to_f64(MPFR_RNDN)
+    }
+
+    #[test]
+    fn gamma_values() {
+        let inf: f64 = Float::infinity();
+        assert_eq!(value(gamma(&Mpfr::from_f64(0.5, 53), 53, MPFR_RNDN)), 1.772453850905516);
+        assert_eq!(value(gamma(&Mpfr::from_f64(5.0, 53), 53, MPFR_RNDN)), 24.0);
+        assert_eq!(value(gamma(&Mpfr::from_f64(-1.5, 53), 53, MPFR_RNDN)), 2.363271801207355);
+        assert_eq!(value(gamma(&Mpfr::from_f64(0.0, 53), 53, MPFR_RNDN)), inf);
+        assert_eq!(value(gamma(&Mpfr::from_f64(-0.0, 53), 53, MPFR_RNDN)), -inf);
+        assert_eq!(value(gamma(&Mpfr::from_f64(inf, 53), 53, MPFR_RNDN)), inf);
+        assert!(gamma(&Mpfr::from_f64(-3.0, 53), 53, MPFR_RNDN).is_err());
+        assert!(gamma(&Mpfr::from_f64(-inf, 53), 53, MPFR_RNDN).is_err());
+    }
+
+    #[test]
+    fn lngamma_values() {
+        let inf: f64 = Float::infinity();
+        let (r, o) = lngamma(&Mpfr::from_f64(1.0, 53), 53, MPFR_RNDN).ok().unwrap();
+        assert!(r.to_f64(MPFR_RNDN) == 0.0 && r.sgn() == 0 && o == Ordering::Equal);
+        assert_eq!(value(lngamma(&Mpfr::from_f64(0.5, 53), 53, MPFR_RNDN)), 0.5723649429247001);
+        assert_eq!(value(lngamma(&Mpfr::from_f64(10.0, 53), 53, MPFR_RNDN)), 12.801827480081469);
+        // Gamma(-1.5) is positive
+        assert_eq!(value(lngamma(&Mpfr::from_f64(-1.5, 53), 53, MPFR_RNDN)), 0.860047015376481);
+
+        // Poles
+        for &x in [0.0, -0.0, -1.0, -4.0, inf, -inf].iter() {
+            let (r, o) = lngamma(&Mpfr::from_f64(x, 53), 53, MPFR_RNDN).ok().unwrap();
+            assert_eq!(r.to_f64(MPFR_RNDN), inf);
+            assert_eq!(o, Ordering::Equal);
+        }
+
+        // Negative Gamma(x)
+        for &x in [-0.5, -2.5, -1e-300].iter() {
+            assert!(lngamma(&Mpfr::from_f64(x, 53), 53, MPFR_RNDN).is_err());
+        }
+    }
+
+    #[test]
+    fn lgamma_values() {
+        let (r, _, sign) = lgamma(&Mpfr::from_f64(-0.5, 53), 53, MPFR_RNDN).ok().unwrap();
+        assert_eq!(r.to_f64(MPFR_RNDN), 1.2655121234846454);
+        assert!(sign == Sign::Negative);
+        let (r, _, sign) = lgamma(&Mpfr::from_f64(0.5, 53), 53, MPFR_RNDN).ok().unwrap();
+        assert_eq!(r.to_f64(MPFR_RNDN), 0.5723649429247001);
+        assert!(sign == Sign::Positive);
+        assert!(lgamma(&Mpfr::from_f64(-2.0, 53), 53, MPFR_RNDN).is_err());
+    }
+}
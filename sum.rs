@@ -0,0 +1,196 @@
+//! Correctly rounded sums with `mpfr_sum`.
+//!
+//! The whole sum is rounded once, so there is no accumulation of rounding errors whatever
+//! the order and magnitude of the terms. With MPFR 3.1 the ternary value of `mpfr_sum` only
+//! tells whether the sum is exact, so these functions return a flag instead of an Ordering.
+
+use std::ptr;
+use std::mem;
+use std::iter::AdditiveIterator;
+use libc::{c_int, c_ulong, c_void};
+use gmp::mp_limb_t;
+
+use super::{__mpfr_struct, mpfr_ptr, mpfr_prec_t, mpfr_rnd_t, MPFR_RNDN, MPFR_ZERO_KIND};
+use float::Mpfr;
+use inline::mpfr_custom_init_set;
+use context;
+
+// Up to this many terms the pointer table (and for f64 the values) are kept on the stack
+const SMALL: usize = 16;
+
+// Limbs for 53 bits with either 32- or 64-bit limbs
+const F64_LIMBS: usize = 2;
+
+unsafe fn sum_ptrs(r: &mut Mpfr, tab: &mut [mpfr_ptr], rnd: mpfr_rnd_t) -> c_int {
+    super::mpfr_sum(r.as_mut_ptr(), tab.as_mut_ptr(), tab.len() as c_ulong, rnd)
+}
+
+// Types which can be summed with mpfr_sum
+pub trait Summand {
+    // Sets `r` to the sum of `xs` rounded to its precision; returns 0 if the sum is exact
+    fn sum_slice(xs: &[Self], r: &mut Mpfr, rnd: mpfr_rnd_t) -> c_int;
+}
+
+impl Summand for Mpfr {
+    fn sum_slice(xs: &[Mpfr], r: &mut Mpfr, rnd: mpfr_rnd_t) -> c_int {
+        // mpfr_sum does not modify the terms despite taking mpfr_ptr
+        if xs.len() <= SMALL {
+            let mut tab: [mpfr_ptr; SMALL] = [ptr::null_mut(); SMALL];
+            for (p, x) in tab.iter_mut().zip(xs.iter()) {
+                *p = x.as_ptr() as mpfr_ptr;
+            }
+            unsafe { sum_ptrs(r, &mut tab[..xs.len()], rnd) }
+        } else {
+            let mut tab: Vec<mpfr_ptr> = xs.iter().map(|x| x.as_ptr() as mpfr_ptr).collect();
+            unsafe { sum_ptrs(r, tab.as_mut_slice(), rnd) }
+        }
+    }
+}
+
+// Every f64 is converted to a 53-bit value, which is exact
+impl Summand for f64 {
+    fn sum_slice(xs: &[f64], r: &mut Mpfr, rnd: mpfr_rnd_t) -> c_int {
+        if xs.len() <= SMALL {
+            let mut limbs: [mp_limb_t; SMALL * F64_LIMBS] = [0; SMALL * F64_LIMBS];
+            let mut values: [__mpfr_struct; SMALL] = unsafe { mem::zeroed() };
+            let mut tab: [mpfr_ptr; SMALL] = [ptr::null_mut(); SMALL];
+            for i in 0..xs.len() {
+                unsafe {
+                    let p = limbs.as_mut_ptr().offset((i * F64_LIMBS) as isize);
+                    mpfr_custom_init_set(&mut values[i], MPFR_ZERO_KIND as c_int, 0, 53,
+                                         p as *mut c_void);
+                    super::mpfr_set_d(&mut values[i], xs[i], MPFR_RNDN);
+                }
+                tab[i] = &mut values[i];
+            }
+            unsafe { sum_ptrs(r, &mut tab[..xs.len()], rnd) }
+        } else {
+            let values: Vec<Mpfr> = xs.iter().map(|&x| Mpfr::from_f64(x, 53)).collect();
+            Summand::sum_slice(values.as_slice(), r, rnd)
+        }
+    }
+}
+
+// Returns the sum of `xs` correctly rounded to `prec` bits, and whether it is exact
+pub fn sum_exact<T: Summand>(xs: &[T], prec: mpfr_prec_t, rnd: mpfr_rnd_t) -> (Mpfr, bool) {
+    let mut r = Mpfr::new(prec);
+    let t = Summand::sum_slice(xs, &mut r, rnd);
+    (r, t == 0)
+}
+
+// Correctly rounded sum of the values produced by an iterator, with the given precision and
+// rounding mode; `AdditiveIterator::sum` uses the defaults of the context module instead
+pub trait SumExact {
+    fn sum_exact(self, prec: mpfr_prec_t, rnd: mpfr_rnd_t) -> (Mpfr, bool);
+}
+
+impl<'a, I: Iterator<Item=&'a Mpfr>> SumExact for I {
+    fn sum_exact(self, prec: mpfr_prec_t, rnd: mpfr_rnd_t) -> (Mpfr, bool) {
+        let mut iter = self;
+        let mut r = Mpfr::new(prec);
+
+        // The first SMALL pointers go on the stack; the table moves to the heap only when
+        // the iterator produces more
+        let mut small: [mpfr_ptr; SMALL] = [ptr::null_mut(); SMALL];
+        let mut n = 0;
+        for x in iter.by_ref() {
+            small[n] = x.as_ptr() as mpfr_ptr;
+            n += 1;
+            if n == SMALL {
+                break;
+            }
+        }
+        let next = if n == SMALL { iter.next() } else { None };
+        let t = match next {
+            None => unsafe { sum_ptrs(&mut r, &mut small[..n], rnd) },
+            Some(x) => {
+                let mut tab: Vec<mpfr_ptr> = small.to_vec();
+                tab.push(x.as_ptr() as mpfr_ptr);
+                tab.extend(iter.map(|x| x.as_ptr() as mpfr_ptr));
+                unsafe { sum_ptrs(&mut r, tab.as_mut_slice(), rnd) }
+            }
+        };
+        (r, t == 0)
+    }
+}
+
+// `xs.iter().sum()`, rounded to the default precision in the default rounding mode, which
+// context::DefaultsGuard can set for a scope
+impl<'a, I: Iterator<Item=&'a Mpfr>> AdditiveIterator<Mpfr> for I {
+    fn sum(self) -> Mpfr {
+        self.sum_exact(context::default_prec(), context::default_rounding_mode()).0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::iter::AdditiveIterator;
+    use std::num::Float;
+
+    use super::{sum_exact, SumExact, SMALL};
+    use super::super::{MPFR_RNDN, MPFR_RNDD};
+    use float::Mpfr;
+    use context::DefaultsGuard;
+
+    fn mpfrs(xs: &[f64]) -> Vec<Mpfr> {
+        xs.iter().map(|&x| Mpfr::from_f64(x, 53)).collect()
+    }
+
+    // Sums of `xs` as f64 values, as Mpfr values and over an iterator, all rounded to 53 bits
+    fn sums(xs: &[f64]) -> Vec<(f64, bool)> {
+        let values = mpfrs(xs);
+        let (a, ea) = sum_exact(xs, 53, MPFR_RNDN);
+        let (b, eb) = sum_exact(values.as_slice(), 53, MPFR_RNDN);
+        let (c, ec) = values.iter().sum_exact(53, MPFR_RNDN);
+        vec![(a.to_f64(MPFR_RNDN), ea), (b.to_f64(MPFR_RNDN), eb), (c.to_f64(MPFR_RNDN), ec)]
+    }
+
+    #[test]
+    fn cancellation() {
+        for s in sums(&[1e308, 1.0, -1e308]).into_iter() {
+            assert_eq!(s, (1.0, true));
+        }
+        // 1 + 2^-60 needs 61 bits
+        for s in sums(&[1e308, 1.0, 2.0f64.powi(-60), -1e308]).into_iter() {
+            assert_eq!(s, (1.0, false));
+        }
+        for s in sums(&[0.1, 0.2, -0.3]).into_iter() {
+            assert_eq!(s, (2.0f64.powi(-55), true));
+        }
+        for s in sums(&[]).into_iter() {
+            assert_eq!(s, (0.0, true));
+        }
+    }
+
+    // SMALL terms are summed from the stack, one more moves the pointer table to the heap
+    #[test]
+    fn stack_and_heap() {
+        for &n in [SMALL - 1, SMALL, SMALL + 1, 3 * SMALL].iter() {
+            // Large terms cancelling each other at both ends, with ones between them
+            let mut xs = vec![1e308];
+            xs.extend((0..n - 2).map(|_| 1.0));
+            xs.push(-1e308);
+            assert_eq!(xs.len(), n);
+            for s in sums(&*xs).into_iter() {
+                assert_eq!(s, ((n - 2) as f64, true));
+            }
+
+            // Distinct powers of two, the last one only being included past SMALL terms
+            let xs: Vec<f64> = (0..n).map(|i| 2.0f64.powi(i as i32)).collect();
+            for s in sums(&*xs).into_iter() {
+                assert_eq!(s, (2.0f64.powi(n as i32) - 1.0, true));
+            }
+        }
+    }
+
+    #[test]
+    fn sum_with_defaults() {
+        let xs = mpfrs(&[1.0, 2.0f64.powi(-30), -2.0f64.powi(-40)]);
+        let _defaults = DefaultsGuard::new(24, MPFR_RNDD);
+        let s: Mpfr = xs.iter().sum();
+        assert_eq!(s.prec(), 24);
+        assert_eq!(s.to_f64(MPFR_RNDN), 1.0);
+        let s: Mpfr = xs[1..].iter().sum();
+        assert_eq!(s.to_f64(MPFR_RNDN), 2.0f64.powi(-30) - 2.0f64.powi(-40));
+    }
+}
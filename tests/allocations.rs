@@ -0,0 +1,143 @@
+#![allow(unstable)]
+
+extern crate libc;
+extern crate "gmp-sys" as gmp;
+extern crate "mpfr-sys" as mpfr;
+
+use std::cell::Cell;
+use std::sync::{Once, ONCE_INIT};
+use std::sync::atomic::{AtomicUsize, AtomicIsize, Ordering};
+use std::sync::atomic::{ATOMIC_USIZE_INIT, ATOMIC_ISIZE_INIT};
+use std::thread::Thread;
+use libc::{c_void, size_t};
+use gmp::mp_limb_t;
+use mpfr::MPFR_RNDN;
+use mpfr::custom::InlineFloat;
+use mpfr::float::Mpfr;
+use mpfr::context;
+
+// GMP memory functions are global to the process, so they are installed once, before any
+// test allocates, and count the calls of each thread separately since tests run in
+// parallel. Allocation goes on through the functions which were installed before.
+
+extern "C" {
+    fn __gmp_set_memory_functions(alloc: extern "C" fn(size_t) -> *mut c_void,
+                                  realloc: extern "C" fn(*mut c_void, size_t, size_t) -> *mut c_void,
+                                  free: extern "C" fn(*mut c_void, size_t));
+    fn pthread_self() -> usize;
+    fn __gmp_get_memory_functions(alloc: *mut Option<extern "C" fn(size_t) -> *mut c_void>,
+                                  realloc: *mut Option<extern "C" fn(*mut c_void, size_t, size_t)
+                                                                     -> *mut c_void>,
+                                  free: *mut Option<extern "C" fn(*mut c_void, size_t)>);
+}
+
+static INSTALL: Once = ONCE_INIT;
+static mut ALLOC: Option<extern "C" fn(size_t) -> *mut c_void> = None;
+static mut REALLOC: Option<extern "C" fn(*mut c_void, size_t, size_t) -> *mut c_void> = None;
+static mut FREE: Option<extern "C" fn(*mut c_void, size_t)> = None;
+
+// Calls of any of the memory functions, and bytes allocated minus bytes freed
+thread_local!(static CALLS: Cell<u64> = Cell::new(0));
+thread_local!(static LIVE: Cell<i64> = Cell::new(0));
+
+// Bytes allocated minus bytes freed by one thread, identified by pthread_self(), including
+// from the destructors of its thread-local variables
+static TRACKED: AtomicUsize = ATOMIC_USIZE_INIT;
+static TRACKED_LIVE: AtomicIsize = ATOMIC_ISIZE_INIT;
+
+fn count(delta: i64) {
+    CALLS.with(|c| c.set(c.get() + 1));
+    LIVE.with(|l| l.set(l.get() + delta));
+    if unsafe { pthread_self() } == TRACKED.load(Ordering::SeqCst) {
+        TRACKED_LIVE.fetch_add(delta as isize, Ordering::SeqCst);
+    }
+}
+
+extern "C" fn counting_alloc(size: size_t) -> *mut c_void {
+    count(size as i64);
+    unsafe { ALLOC.unwrap()(size) }
+}
+
+extern "C" fn counting_realloc(ptr: *mut c_void, old_size: size_t, new_size: size_t) -> *mut c_void {
+    count(new_size as i64 - old_size as i64);
+    unsafe { REALLOC.unwrap()(ptr, old_size, new_size) }
+}
+
+extern "C" fn counting_free(ptr: *mut c_void, size: size_t) {
+    count(-(size as i64));
+    unsafe { FREE.unwrap()(ptr, size) }
+}
+
+fn install() {
+    INSTALL.call_once(|| unsafe {
+        __gmp_get_memory_functions(&mut ALLOC, &mut REALLOC, &mut FREE);
+        __gmp_set_memory_functions(counting_alloc, counting_realloc, counting_free);
+    });
+}
+
+// Runs f and returns the number of memory function calls it made and the balance of bytes
+fn allocations<F: FnOnce()>(f: F) -> (u64, i64) {
+    install();
+    let (calls, live) = (CALLS.with(|c| c.get()), LIVE.with(|l| l.get()));
+    f();
+    (CALLS.with(|c| c.get()) - calls, LIVE.with(|l| l.get()) - live)
+}
+
+#[test]
+fn inline_float_does_not_allocate() {
+    let (calls, _) = allocations(|| {
+        // Four limbs hold 100 bits with 32-bit limbs too
+        let mut x: InlineFloat<[mp_limb_t; 4]> = InlineFloat::new(100);
+        let mut y: InlineFloat<[mp_limb_t; 4]> = InlineFloat::new(100);
+        unsafe {
+            mpfr::mpfr_set_d(x.as_mut_ptr(), 1.5, MPFR_RNDN);
+            mpfr::mpfr_set_ui(y.as_mut_ptr(), 3, MPFR_RNDN);
+            mpfr::mpfr_mul(x.as_mut_ptr(), x.as_ptr(), y.as_ptr(), MPFR_RNDN);
+            mpfr::mpfr_add(y.as_mut_ptr(), x.as_ptr(), y.as_ptr(), MPFR_RNDN);
+            mpfr::mpfr_sqrt(y.as_mut_ptr(), y.as_ptr(), MPFR_RNDN);
+            // Moved values are re-pointed to their own limbs
+            let z = y.clone();
+            let moved = vec![z];
+            assert!(mpfr::mpfr_cmp(moved[0].as_ptr(), y.as_ptr()) == 0);
+            assert!(mpfr::mpfr_cmp_d(x.as_ptr(), 4.5) == 0);
+        }
+    });
+    assert_eq!(calls, 0);
+}
+
+#[test]
+fn exp_allocations_balance() {
+    let x = Mpfr::from_f64(0.7, 2000);
+    let mut r = Mpfr::new(2000);
+    let (calls, live) = allocations(|| unsafe {
+        mpfr::mpfr_exp(r.as_mut_ptr(), x.as_ptr(), MPFR_RNDN);
+        mpfr::mpfr_free_cache();
+    });
+    assert!(calls > 0);
+    // Every temporary and the cached constants are released with the sizes they were
+    // allocated with
+    assert_eq!(live, 0);
+
+    let (calls, live) = allocations(|| {
+        let y = Mpfr::new(2000);
+        drop(y);
+    });
+    assert_eq!((calls, live), (2, 0));
+}
+
+#[test]
+fn caches_are_freed_on_thread_exit() {
+    install();
+    let cached = Thread::scoped(|| {
+        TRACKED.store(unsafe { pthread_self() }, Ordering::SeqCst);
+        context::free_cache_on_thread_exit();
+        let mut pi = Mpfr::new(10000);
+        unsafe { mpfr::mpfr_const_pi(pi.as_mut_ptr(), MPFR_RNDN) };
+        drop(pi);
+        // Only the cache of pi is left
+        TRACKED_LIVE.load(Ordering::SeqCst)
+    }).join().ok().unwrap();
+    assert!(cached > 0);
+    // The thread has exited, its thread-local destructors included
+    assert_eq!(TRACKED_LIVE.load(Ordering::SeqCst), 0);
+}
@@ -0,0 +1,105 @@
+#![allow(unstable)]
+
+extern crate libc;
+extern crate "mpfr-sys" as mpfr;
+
+use std::ffi::CString;
+use std::ptr;
+use std::sync::atomic::{AtomicUsize, AtomicIsize, Ordering, ATOMIC_USIZE_INIT, ATOMIC_ISIZE_INIT};
+use libc::{c_char, c_void, size_t};
+use mpfr::MPFR_RNDN;
+use mpfr::float::Mpfr;
+use mpfr::memory::{use_rust_allocator, MpfrStr};
+
+// A separate test binary: use_rust_allocator() has to run before anything in the process
+// allocates through GMP, which is why this file has a single test
+
+extern "C" {
+    fn __gmp_set_memory_functions(alloc: extern "C" fn(size_t) -> *mut c_void,
+                                  realloc: extern "C" fn(*mut c_void, size_t, size_t)
+                                                         -> *mut c_void,
+                                  free: extern "C" fn(*mut c_void, size_t));
+    fn __gmp_get_memory_functions(alloc: *mut Option<extern "C" fn(size_t) -> *mut c_void>,
+                                  realloc: *mut Option<extern "C" fn(*mut c_void, size_t, size_t)
+                                                                     -> *mut c_void>,
+                                  free: *mut Option<extern "C" fn(*mut c_void, size_t)>);
+}
+
+// The functions installed by use_rust_allocator(), which the counting ones call
+static mut ALLOC: Option<extern "C" fn(size_t) -> *mut c_void> = None;
+static mut REALLOC: Option<extern "C" fn(*mut c_void, size_t, size_t) -> *mut c_void> = None;
+static mut FREE: Option<extern "C" fn(*mut c_void, size_t)> = None;
+
+// Calls of the allocation and free functions, and bytes allocated minus bytes freed; the
+// only test of this binary is the only thread using GMP
+static ALLOCS: AtomicUsize = ATOMIC_USIZE_INIT;
+static FREES: AtomicUsize = ATOMIC_USIZE_INIT;
+static LIVE: AtomicIsize = ATOMIC_ISIZE_INIT;
+
+extern "C" fn counting_alloc(size: size_t) -> *mut c_void {
+    ALLOCS.fetch_add(1, Ordering::SeqCst);
+    LIVE.fetch_add(size as isize, Ordering::SeqCst);
+    unsafe { ALLOC.unwrap()(size) }
+}
+
+extern "C" fn counting_realloc(ptr: *mut c_void, old_size: size_t, new_size: size_t)
+                               -> *mut c_void {
+    LIVE.fetch_add(new_size as isize - old_size as isize, Ordering::SeqCst);
+    unsafe { REALLOC.unwrap()(ptr, old_size, new_size) }
+}
+
+extern "C" fn counting_free(ptr: *mut c_void, size: size_t) {
+    FREES.fetch_add(1, Ordering::SeqCst);
+    LIVE.fetch_add(-(size as isize), Ordering::SeqCst);
+    unsafe { FREE.unwrap()(ptr, size) }
+}
+
+fn mpfr_str_round_trip() {
+    let x = Mpfr::from_f64(-1234.5, 53);
+    let mut exp = 0;
+    let s = unsafe {
+        MpfrStr::from_raw(mpfr::mpfr_get_str(ptr::null_mut(), &mut exp, 10, 0, x.as_ptr(),
+                                             MPFR_RNDN))
+    };
+    assert_eq!(s.as_bytes(), b"-12345000000000000");
+    assert_eq!(exp, 4);
+
+    // -0.12345000000000000e4
+    let mut text = b"-0.".to_vec();
+    text.push_all(&s.as_bytes()[1..]);
+    text.push_all(format!("e{}", exp).as_bytes());
+    let text = CString::from_slice(&*text);
+    let mut y = Mpfr::new(53);
+    unsafe {
+        assert_eq!(mpfr::mpfr_set_str(y.as_mut_ptr(), text.as_ptr() as *const c_char, 10,
+                                      MPFR_RNDN), 0);
+        assert!(mpfr::mpfr_equal_p(x.as_ptr(), y.as_ptr()) != 0);
+    }
+}
+
+#[test]
+fn rust_allocator() {
+    unsafe {
+        use_rust_allocator();
+        __gmp_get_memory_functions(&mut ALLOC, &mut REALLOC, &mut FREE);
+        __gmp_set_memory_functions(counting_alloc, counting_realloc, counting_free);
+    }
+
+    mpfr_str_round_trip();
+
+    // Temporaries of exp and the cached constants at high precision, all going through the
+    // Rust allocator and given back to it with the sizes they were allocated with
+    let x = Mpfr::from_f64(0.7, 20000);
+    let mut r = Mpfr::new(20000);
+    unsafe {
+        mpfr::mpfr_exp(r.as_mut_ptr(), x.as_ptr(), MPFR_RNDN);
+        assert!(mpfr::mpfr_cmp_d(r.as_ptr(), 2.0137527074704766) > 0);
+    }
+    drop(x);
+    drop(r);
+    unsafe { mpfr::mpfr_free_cache() };
+
+    assert!(ALLOCS.load(Ordering::SeqCst) > 2);
+    assert_eq!(ALLOCS.load(Ordering::SeqCst), FREES.load(Ordering::SeqCst));
+    assert_eq!(LIVE.load(Ordering::SeqCst), 0);
+}
@@ -0,0 +1,238 @@
+//! Measuring the accuracy of f64 and f32 implementations of math functions.
+//!
+//! A function under test is compared with the MPFR function computing the same thing:
+//! the correctly rounded result in the chosen rounding mode tells whether the tested result
+//! is correctly rounded, and a more precise MPFR value gives its error in ulps. Inputs come
+//! from a uniform random generator, a regular sweep of a range, or, for f32, all values.
+//!
+//! f32 functions are tested through f64 closures, e.g. `|x| sinf(x as f32) as f64` with
+//! `Format::Binary32`; inputs are rounded to f32 first.
+
+use std::mem;
+use std::f64;
+use std::num::Float;
+use libc::c_double;
+
+use super::{mpfr_rnd_t, mpfr_exp_t, MPFR_RNDN};
+use float::{Mpfr, Fn1};
+use inline::{mpfr_get_exp, mpfr_regular_p};
+
+pub use cr::Format;
+
+// An MPFR function of one argument, e.g. mpfr_sin
+pub type Reference = Fn1;
+
+// Exponent of the smallest normal number, in MPFR terms
+fn emin_normal(format: Format) -> mpfr_exp_t {
+    match format { Format::Binary32 => -125, Format::Binary64 => -1021 }
+}
+
+fn round_input(format: Format, x: f64) -> f64 {
+    match format { Format::Binary32 => x as f32 as f64, Format::Binary64 => x }
+}
+
+// Same value, NaNs being all equal and zeros of different signs not
+fn same(x: f64, y: f64) -> bool {
+    (x.is_nan() && y.is_nan()) ||
+        unsafe { mem::transmute::<f64, u64>(x) == mem::transmute::<f64, u64>(y) }
+}
+
+// One of the worst cases found
+#[derive(Copy)]
+pub struct Case {
+    pub input: f64,
+    pub result: f64,
+    pub expected: f64,
+    pub ulps: f64
+}
+
+pub struct Report {
+    pub tested: u64,
+    // Results differing from the correctly rounded ones
+    pub incorrect: u64,
+    // Largest error of all results, correctly rounded ones included, e.g. up to 0.5 for
+    // correctly rounded results to nearest
+    pub max_ulps: f64,
+    // Worst incorrectly rounded cases by decreasing error
+    pub worst: Vec<Case>
+}
+
+// xorshift64*, enough for spreading inputs
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0 * 2685821657736338717
+    }
+
+    // Uniform in [0, 1)
+    fn next_f64(&mut self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+pub struct Harness<F> {
+    f: F,
+    reference: Reference,
+    format: Format,
+    rnd: mpfr_rnd_t,
+    keep: usize,
+    report: Report
+}
+
+impl<F: Fn(f64) -> f64> Harness<F> {
+    // Tests f against the MPFR function `reference` for results rounded in the direction
+    // `rnd`, keeping the 10 worst cases
+    pub fn new(f: F, reference: Reference, format: Format, rnd: mpfr_rnd_t) -> Harness<F> {
+        Harness {
+            f: f,
+            reference: reference,
+            format: format,
+            rnd: rnd,
+            keep: 10,
+            report: Report { tested: 0, incorrect: 0, max_ulps: 0.0, worst: Vec::new() }
+        }
+    }
+
+    #[inline]
+    pub fn report(&self) -> &Report { &self.report }
+
+    // Error of y in ulps of the exact f(x), computed with 64 extra bits; `correct` tells
+    // whether y is the correctly rounded result
+    fn ulps(&self, x: f64, y: f64, correct: bool) -> f64 {
+        let prec = self.format.prec() + 64;
+        let xm = Mpfr::from_f64(x, 53);
+        let mut exact = Mpfr::new(prec);
+        let mut d = Mpfr::new(prec);
+        unsafe {
+            (self.reference)(exact.as_mut_ptr(), xm.as_ptr(), MPFR_RNDN);
+            if exact.is_nan() || y.is_nan() {
+                return if exact.is_nan() && y.is_nan() { 0.0 } else { f64::INFINITY };
+            }
+            // Beyond the range of the format, the correctly rounded result is an infinity or the
+            // largest finite value, and the distance to the exact value is not an error
+            if correct && mpfr_regular_p(exact.as_ptr()) != 0 &&
+                mpfr_get_exp(exact.as_ptr()) > self.format.emax() {
+                return 0.0;
+            }
+            super::mpfr_sub_d(d.as_mut_ptr(), exact.as_ptr(), y as c_double, MPFR_RNDN);
+            if d.is_nan() {
+                // Infinities of the same sign
+                return 0.0;
+            }
+            let e = if mpfr_regular_p(exact.as_ptr()) != 0 {
+                ::std::cmp::max(mpfr_get_exp(exact.as_ptr()), emin_normal(self.format))
+            } else {
+                emin_normal(self.format)
+            };
+            let shift = (self.format.prec() as mpfr_exp_t - e) as ::libc::c_long;
+            super::mpfr_mul_2si(d.as_mut_ptr(), d.as_ptr(), shift, MPFR_RNDN);
+            d.to_f64(MPFR_RNDN).abs()
+        }
+    }
+
+    pub fn check(&mut self, x: f64) {
+        let x = round_input(self.format, x);
+        let y = (self.f)(x);
+        let expected = self.format.correctly_rounded(self.reference, x, self.rnd);
+        self.report.tested += 1;
+        let correct = same(y, expected);
+        let ulps = self.ulps(x, y, correct);
+        if ulps > self.report.max_ulps {
+            self.report.max_ulps = ulps;
+        }
+        if correct {
+            return;
+        }
+        self.report.incorrect += 1;
+
+        let worst = &mut self.report.worst;
+        if worst.len() < self.keep || ulps > worst[worst.len() - 1].ulps {
+            let pos = worst.iter().position(|c| ulps > c.ulps).unwrap_or(worst.len());
+            worst.insert(pos, Case { input: x, result: y, expected: expected, ulps: ulps });
+            worst.truncate(self.keep);
+        }
+    }
+
+    // `count` inputs uniformly distributed in [lo, hi)
+    pub fn random(&mut self, lo: f64, hi: f64, count: u64, seed: u64) {
+        let mut rng = Rng(if seed == 0 { 0x9e3779b97f4a7c15 } else { seed });
+        for _ in 0..count {
+            let x = lo + (hi - lo) * rng.next_f64();
+            self.check(x);
+        }
+    }
+
+    // `count` equally spaced inputs from lo to hi inclusive
+    pub fn sweep(&mut self, lo: f64, hi: f64, count: u64) {
+        for i in 0..count {
+            let t = if count == 1 { 0.0 } else { i as f64 / (count - 1) as f64 };
+            self.check(lo + (hi - lo) * t);
+        }
+    }
+
+    // Every f32 value, NaNs excepted; only for Format::Binary32
+    pub fn exhaustive_f32(&mut self) {
+        assert!(self.format == Format::Binary32, "exhaustive testing needs Format::Binary32");
+        let mut bits = 0u32;
+        loop {
+            let x: f32 = unsafe { mem::transmute(bits) };
+            if !x.is_nan() {
+                self.check(x as f64);
+            }
+            if bits == !0 {
+                break;
+            }
+            bits += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::num::Float;
+
+    use super::{Harness, Format};
+    use super::super::{mpfr_sqrt, mpfr_exp, MPFR_RNDN, MPFR_RNDZ};
+    use cr::cr_exp;
+
+    #[test]
+    fn correctly_rounded_results_count_in_max_ulps() {
+        // Square root is correctly rounded in IEEE 754
+        let mut h = Harness::new(|x: f64| x.sqrt(), mpfr_sqrt, Format::Binary64, MPFR_RNDN);
+        h.random(1.0, 4.0, 1000, 1);
+        let report = h.report();
+        assert_eq!(report.tested, 1000);
+        assert_eq!(report.incorrect, 0);
+        assert!(report.worst.is_empty());
+        assert!(0.0 < report.max_ulps && report.max_ulps <= 0.5, "{}", report.max_ulps);
+    }
+
+    #[test]
+    fn overflow_is_not_an_error() {
+        // exp overflows f64 beyond about 709.78
+        for &rnd in [MPFR_RNDN, MPFR_RNDZ].iter() {
+            let mut h = Harness::new(|x: f64| cr_exp(x, rnd), mpfr_exp, Format::Binary64, rnd);
+            h.sweep(709.0, 711.0, 201);
+            let report = h.report();
+            assert_eq!(report.incorrect, 0);
+            assert!(report.max_ulps < 1.0, "{}", report.max_ulps);
+        }
+    }
+
+    #[test]
+    fn incorrect_results_are_kept() {
+        // exp with only the precision of f32
+        let mut h = Harness::new(|x: f64| (x.exp() as f32) as f64, mpfr_exp, Format::Binary64,
+                                 MPFR_RNDN);
+        h.sweep(0.0, 1.0, 100);
+        let report = h.report();
+        assert!(report.incorrect > 0);
+        assert_eq!(report.worst.len(), 10);
+        assert_eq!(report.worst[0].ulps, report.max_ulps);
+        assert!(report.max_ulps > 1.0);
+    }
+}
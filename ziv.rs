@@ -0,0 +1,159 @@
+//! Ziv's strategy for correctly rounded evaluation of user-defined functions.
+//!
+//! The function is evaluated at some working precision together with a bound on its error;
+//! if `mpfr_can_round` shows that the approximation determines the correctly rounded result,
+//! it is rounded to the target precision, otherwise the working precision is increased.
+
+use std::cmp::{self, Ordering};
+
+use super::{mpfr_prec_t, mpfr_rnd_t, mpfr_exp_t, MPFR_RNDN, MPFR_RNDZ};
+use float::{Mpfr, ordering};
+use inline::mpfr_regular_p;
+
+// An approximation computed at working precision
+pub enum Approx {
+    // The value is exact; this is the only way to return zero, infinities or NaN
+    Exact(Mpfr),
+    // The value has an error of at most 2^k ulps of itself, for the given k
+    Within(Mpfr, mpfr_prec_t)
+}
+
+// Number of bits of x, i.e. the ceiling of log2(x + 1)
+fn bit_length(x: mpfr_prec_t) -> mpfr_prec_t {
+    let mut n = 0;
+    let mut x = x;
+    while x > 0 {
+        n += 1;
+        x >>= 1;
+    }
+    n
+}
+
+// Returns f correctly rounded to `prec` bits in the direction `rnd`, with the direction of
+// rounding relative to the exact value, or None if the working precision would exceed
+// `max_prec` (which happens e.g. when the exact value is representable but `f` only ever
+// returns `Within` approximations).
+//
+// `f` is called with the working precision, and must return an approximation of the exact
+// value with that precision.
+pub fn ziv<F: FnMut(mpfr_prec_t) -> Approx>(prec: mpfr_prec_t, rnd: mpfr_rnd_t, max_prec: mpfr_prec_t,
+                                            mut f: F) -> Option<(Mpfr, Ordering)> {
+    let mut w = prec + bit_length(prec) + 10;
+    let mut step = 64;
+    // The trick from the MPFR manual: rounding toward zero to one more bit for round to
+    // nearest rejects approximations too close to a midpoint, and makes the ternary value
+    // correct too. It requires the exact value not to be representable in `prec` bits,
+    // which is the case when `f` does not return it as Exact.
+    let target = prec + (rnd == MPFR_RNDN) as mpfr_prec_t;
+
+    while w <= max_prec {
+        let approx = match f(w) {
+            Approx::Exact(a) => a,
+            Approx::Within(a, k) => {
+                let err = (a.prec() - k) as mpfr_exp_t;
+                let ok = unsafe {
+                    mpfr_regular_p(a.as_ptr()) != 0 &&
+                        super::mpfr_can_round(a.as_ptr(), err, MPFR_RNDN, MPFR_RNDZ, target) != 0
+                };
+                if !ok {
+                    w += step;
+                    step = cmp::max(step, w / 2);
+                    continue;
+                }
+                a
+            }
+        };
+        let mut r = Mpfr::new(prec);
+        let t = unsafe { super::mpfr_set(r.as_mut_ptr(), approx.as_ptr(), rnd) };
+        return Some((r, ordering(t)));
+    }
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use std::cmp::{self, Ordering};
+    use libc::c_ulong;
+
+    use super::{ziv, Approx};
+    use super::super::{mpfr_prec_t, mpfr_rnd_t, MPFR_RNDN, MPFR_RNDD, MPFR_RNDU, MPFR_RNDZ};
+    use float::{Mpfr, ordering};
+    use inline::{mpfr_get_exp, mpfr_regular_p};
+
+    // exp(x) - 1 - x, which cancels for small x
+    fn expm1mx(x: &Mpfr, prec: mpfr_prec_t, rnd: mpfr_rnd_t) -> (Mpfr, Ordering) {
+        ziv(prec, rnd, 1 << 16, |w| {
+            let mut e = Mpfr::new(w);
+            let mut d = Mpfr::new(w);
+            let mut y = Mpfr::new(w);
+            unsafe {
+                super::super::mpfr_exp(e.as_mut_ptr(), x.as_ptr(), MPFR_RNDN);
+                super::super::mpfr_sub_ui(d.as_mut_ptr(), e.as_ptr(), 1, MPFR_RNDN);
+                super::super::mpfr_sub(y.as_mut_ptr(), d.as_ptr(), x.as_ptr(), MPFR_RNDN);
+                if mpfr_regular_p(d.as_ptr()) == 0 || mpfr_regular_p(y.as_ptr()) == 0 {
+                    // Cancelled completely, retry with more bits
+                    return Approx::Within(y, w);
+                }
+                // Three roundings of at most half an ulp of e, d and y respectively, which
+                // is less than 2^(m - w + 1) for the largest exponent m of the three
+                let m = cmp::max(mpfr_get_exp(e.as_ptr()),
+                                 cmp::max(mpfr_get_exp(d.as_ptr()), mpfr_get_exp(y.as_ptr())));
+                Approx::Within(y, (m - mpfr_get_exp(y.as_ptr()) + 1) as mpfr_prec_t)
+            }
+        }).expect("working precision exceeded")
+    }
+
+    // Rounds a value computed with 4000 bits
+    fn rounded(y: &Mpfr, prec: mpfr_prec_t, rnd: mpfr_rnd_t) -> (Mpfr, Ordering) {
+        let mut r = Mpfr::new(prec);
+        let t = unsafe { super::super::mpfr_set(r.as_mut_ptr(), y.as_ptr(), rnd) };
+        (r, ordering(t))
+    }
+
+    fn equal(x: &Mpfr, y: &Mpfr) -> bool {
+        unsafe { super::super::mpfr_equal_p(x.as_ptr(), y.as_ptr()) != 0 }
+    }
+
+    #[test]
+    fn matches_high_precision_reference() {
+        for &p in [1e-30, -1e-12, 1e-5, 0.5, -3.0, 20.0].iter() {
+            let x = Mpfr::from_f64(p, 53);
+            let mut y = Mpfr::new(4000);
+            unsafe {
+                super::super::mpfr_expm1(y.as_mut_ptr(), x.as_ptr(), MPFR_RNDN);
+                super::super::mpfr_sub(y.as_mut_ptr(), y.as_ptr(), x.as_ptr(), MPFR_RNDN);
+            }
+            for &rnd in [MPFR_RNDN, MPFR_RNDD, MPFR_RNDU, MPFR_RNDZ].iter() {
+                let (r, o) = expm1mx(&x, 53, rnd);
+                let (expected, expected_o) = rounded(&y, 53, rnd);
+                assert!(equal(&r, &expected), "mismatch for x = {} and rounding mode {}", p, rnd);
+                assert!(o == expected_o, "wrong ternary for x = {} and rounding mode {}", p, rnd);
+            }
+        }
+    }
+
+    // 1 + 2^-53 + 2^-200 rounds at any working precision below 201 bits to 1 + 2^-53, the
+    // midpoint of 1 and 1 + 2^-52, which must not be taken for the result
+    #[test]
+    fn approximation_on_a_midpoint() {
+        let mut v = Mpfr::new(300);
+        let mut t = Mpfr::new(300);
+        unsafe {
+            super::super::mpfr_set_ui_2exp(v.as_mut_ptr(), 1 as c_ulong, -53, MPFR_RNDN);
+            super::super::mpfr_set_ui_2exp(t.as_mut_ptr(), 1 as c_ulong, -200, MPFR_RNDN);
+            super::super::mpfr_add(v.as_mut_ptr(), v.as_ptr(), t.as_ptr(), MPFR_RNDN);
+            super::super::mpfr_add_ui(v.as_mut_ptr(), v.as_ptr(), 1 as c_ulong, MPFR_RNDN);
+        }
+        for &rnd in [MPFR_RNDN, MPFR_RNDD, MPFR_RNDU, MPFR_RNDZ].iter() {
+            let (r, o) = ziv(53, rnd, 1000, |w| {
+                // Correctly rounded to nearest, so within half an ulp
+                let mut a = Mpfr::new(w);
+                unsafe { super::super::mpfr_set(a.as_mut_ptr(), v.as_ptr(), MPFR_RNDN) };
+                Approx::Within(a, 0)
+            }).expect("working precision exceeded");
+            let (expected, expected_o) = rounded(&v, 53, rnd);
+            assert!(equal(&r, &expected), "mismatch for rounding mode {}", rnd);
+            assert!(o == expected_o, "wrong ternary for rounding mode {}", rnd);
+        }
+    }
+}